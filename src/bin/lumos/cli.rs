@@ -0,0 +1,505 @@
+//! Command-line parsing.
+//!
+//! Options can be given before or after the command, either as
+//! `--option value` or `--option=value`.
+
+use std::time::Duration;
+
+use lumos::{
+    Background, Classifier, Color, DEFAULT_CACHE_TTL, DEFAULT_TIMEOUT, LumosError, parse_rgb,
+};
+
+use crate::export::Shell;
+
+/// Full help, printed by `--help`.
+pub const HELP: &str = "\
+Detect whether the terminal background is dark or light.
+
+Usage: lumos [COMMAND] [OPTIONS]
+
+Commands:
+  detect                 Print dark, light (or medium) or unknown (default)
+  query fg|bg            Print the terminal foreground or background color
+  query palette [16|256] Print the terminal palette, one entry per line
+  classify [COLOR]...    Print whether colors are dark or light, reading stdin
+                         one color per line without any
+  contrast [FG BG]       Print the WCAG and APCA contrasts of text over a background,
+                         querying the terminal colors without any
+  watch                  Print the background again each time it changes
+  invalidate             Forget the cached detection of this terminal session
+
+Options:
+  --timeout <MS>         Give up if the terminal does not answer in time [default: 1000]
+  --format <FORMAT>      Output format: text, json, sh, fish or nu [default: text]
+  --classifier <NAME>    Lightness measure: luminance, cielab or oklab [default: luminance]
+  --threshold <LUM>      Lightness below which a color is dark [default: 0.5]
+  --medium-band <LUM>    Classify colors within threshold ± LUM as medium [default: 0]
+  --hysteresis <LUM>     Keep the previous classification within LUM of its range [default: 0]
+  --previous <BG>        Previous classification (dark, medium or light), for the hysteresis
+  --backdrop <COLOR>     Color behind translucent backgrounds [default: black]
+  --interval <MS>        Time between two detections of watch [default: 1000]
+  --expect <BG>          Make classify fail unless every color is dark (or medium, or light)
+  --cache[=MS]           Reuse the detection of this terminal session for MS [default: 60000]
+  --json                 Same as --format=json
+  --export[=SHELL]       Same as --format=SHELL [default: sh]
+  --palette[=16|256]     Same as query palette
+  -h, --help             Print help
+  -V, --version          Print version
+
+Classifier, threshold, medium band, hysteresis and backdrop can also be set
+with LUMOS_CLASSIFIER, LUMOS_THRESHOLD, LUMOS_MEDIUM_BAND, LUMOS_HYSTERESIS and
+LUMOS_BACKDROP, or in $XDG_CONFIG_HOME/lumos/config (threshold = 0.4...).";
+
+/// Short usage, printed on invalid arguments.
+pub const USAGE: &str = "usage: lumos [COMMAND] [OPTIONS], see lumos --help";
+
+/// Default time between two detections of `lumos watch`.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the terminal is queried for by `lumos query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    /// Default foreground color.
+    Foreground,
+    /// Default background color.
+    Background,
+    /// The given number of palette entries.
+    Palette(u16),
+}
+
+/// Command to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Detect whether the terminal background is dark or light.
+    Detect,
+    /// Print terminal colors.
+    Query(Query),
+    /// Classify colors given on the command line, or read from stdin when
+    /// there are none.
+    Classify(Vec<String>),
+    /// Compute the contrast of foreground and background colors given on
+    /// the command line, or queried from the terminal when there are none.
+    Contrast(Option<(String, String)>),
+    /// Detect the background again and again, printing every change.
+    Watch,
+    /// Remove the cached detection of the terminal session.
+    Invalidate,
+    /// Print help.
+    Help,
+    /// Print version.
+    Version,
+}
+
+/// Output format of the commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Plain text.
+    Text,
+    /// JSON objects.
+    Json,
+    /// Shell assignments in the given syntax, only supported by `detect`.
+    Export(Shell),
+}
+
+impl Format {
+    /// Parse an output format name.
+    fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Shell::from_name(name).map(Self::Export).ok_or_else(|| {
+                format!("invalid format {name:?}: expected text, json, sh, fish or nu")
+            }),
+        }
+    }
+}
+
+/// Command-line arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    /// Command to run.
+    pub command: Command,
+    /// Maximum time to wait for the terminal to answer.
+    pub timeout: Duration,
+    /// Output format.
+    pub format: Format,
+    /// How to measure lightness, overriding the configuration.
+    pub classifier: Option<Classifier>,
+    /// Lightness below which a color is dark, overriding the configuration.
+    pub threshold: Option<f64>,
+    /// Half width of the band around the threshold classified as medium,
+    /// overriding the configuration.
+    pub medium_band: Option<f64>,
+    /// Distance to the previous classification keeping it, overriding the
+    /// configuration.
+    pub hysteresis: Option<f64>,
+    /// Previous classification, for the hysteresis.
+    pub previous: Option<Background>,
+    /// Color behind translucent backgrounds, overriding the configuration.
+    pub backdrop: Option<Color>,
+    /// Time between two detections of `lumos watch`.
+    pub interval: Duration,
+    /// Classification every color given to `lumos classify` must have.
+    pub expect: Option<Background>,
+    /// How long `lumos detect` reuses a cached detection, if caching.
+    pub cache: Option<Duration>,
+}
+
+/// Parse command-line arguments, without the program name.
+///
+/// # Returns
+///
+/// - `Ok(Args)` with the parsed command and options
+/// - `Err(String)` describing the first invalid argument
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut timeout = DEFAULT_TIMEOUT;
+    let mut format = Format::Text;
+    let mut classifier = None;
+    let mut threshold = None;
+    let mut medium_band = None;
+    let mut hysteresis = None;
+    let mut previous = None;
+    let mut backdrop = None;
+    let mut interval = DEFAULT_INTERVAL;
+    let mut expect = None;
+    let mut cache = None;
+    let mut palette = None;
+    let mut positionals = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            positionals.push(arg);
+            continue;
+        }
+
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+
+        match name {
+            "-h" | "--help" => return Ok(Args::new(Command::Help)),
+            "-V" | "--version" => return Ok(Args::new(Command::Version)),
+            "--timeout" => timeout = parse_millis("timeout", &value()?)?,
+            "--interval" => interval = parse_millis("interval", &value()?)?,
+            "--classifier" => classifier = Some(parse_classifier(&value()?)?),
+            "--threshold" => threshold = Some(parse_threshold(&value()?)?),
+            "--medium-band" => medium_band = Some(parse_band("medium band", &value()?)?),
+            "--hysteresis" => hysteresis = Some(parse_band("hysteresis", &value()?)?),
+            "--previous" => previous = Some(parse_background(&value()?)?),
+            "--backdrop" => backdrop = Some(parse_backdrop(&value()?)?),
+            "--format" => format = Format::from_name(&value()?)?,
+            "--expect" => expect = Some(parse_background(&value()?)?),
+            "--json" if inline.is_none() => format = Format::Json,
+            "--export" => {
+                let shell = inline.as_deref().unwrap_or("sh");
+                format =
+                    Format::Export(Shell::from_name(shell).ok_or_else(|| {
+                        format!("invalid shell {shell:?}: expected sh, fish or nu")
+                    })?);
+            }
+            "--palette" => palette = Some(parse_palette_size(inline.as_deref())?),
+            "--cache" => {
+                cache = Some(match inline.as_deref() {
+                    Some(ttl) => parse_millis("cache", ttl)?,
+                    None => DEFAULT_CACHE_TTL,
+                });
+            }
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let command = match palette {
+        Some(size) if positionals.is_empty() => Command::Query(Query::Palette(size)),
+        Some(_) => return Err("--palette cannot be combined with a command".to_string()),
+        None => parse_command(positionals)?,
+    };
+
+    if matches!(format, Format::Export(_)) && command != Command::Detect {
+        return Err("shell formats are only supported by detect".to_string());
+    }
+    if expect.is_some() && !matches!(command, Command::Classify(_)) {
+        return Err("--expect is only supported by classify".to_string());
+    }
+    if cache.is_some() && command != Command::Detect {
+        return Err("--cache is only supported by detect".to_string());
+    }
+
+    Ok(Args {
+        command,
+        timeout,
+        format,
+        classifier,
+        threshold,
+        medium_band,
+        hysteresis,
+        previous,
+        backdrop,
+        interval,
+        expect,
+        cache,
+    })
+}
+
+impl Args {
+    /// Arguments running `command` with default options.
+    fn new(command: Command) -> Self {
+        Self {
+            command,
+            timeout: DEFAULT_TIMEOUT,
+            format: Format::Text,
+            classifier: None,
+            threshold: None,
+            medium_band: None,
+            hysteresis: None,
+            previous: None,
+            backdrop: None,
+            interval: DEFAULT_INTERVAL,
+            expect: None,
+            cache: None,
+        }
+    }
+}
+
+/// Parse the command and its arguments.
+fn parse_command(positionals: Vec<String>) -> Result<Command, String> {
+    let mut positionals = positionals.into_iter();
+    let Some(name) = positionals.next() else {
+        return Ok(Command::Detect);
+    };
+    let rest: Vec<String> = positionals.collect();
+
+    let command = match (name.as_str(), rest.as_slice()) {
+        ("detect", []) => Command::Detect,
+        ("watch", []) => Command::Watch,
+        ("invalidate", []) => Command::Invalidate,
+        ("query", [what]) => match what.as_str() {
+            "fg" | "foreground" => Command::Query(Query::Foreground),
+            "bg" | "background" => Command::Query(Query::Background),
+            "palette" => Command::Query(Query::Palette(16)),
+            _ => {
+                return Err(format!(
+                    "invalid query {what:?}: expected fg, bg or palette"
+                ));
+            }
+        },
+        ("query", [what, size]) if what == "palette" => {
+            Command::Query(Query::Palette(parse_palette_size(Some(size))?))
+        }
+        ("query", _) => return Err("query requires one of fg, bg or palette".to_string()),
+        ("classify", [stdin]) if stdin == "-" => Command::Classify(Vec::new()),
+        ("classify", colors) => Command::Classify(colors.to_vec()),
+        ("contrast", []) => Command::Contrast(None),
+        ("contrast", [fg, bg]) => Command::Contrast(Some((fg.clone(), bg.clone()))),
+        ("contrast", _) => {
+            return Err("contrast requires a foreground and a background color".to_string());
+        }
+        ("detect" | "watch" | "invalidate", [extra, ..]) => {
+            return Err(format!("unexpected argument: {extra}"));
+        }
+        _ => return Err(format!("unknown command: {name}")),
+    };
+
+    Ok(command)
+}
+
+/// Parse a duration given in milliseconds.
+fn parse_millis(what: &str, value: &str) -> Result<Duration, String> {
+    value
+        .parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|e| format!("invalid {what} {value:?}: {e}"))
+}
+
+/// Parse a classifier name.
+pub fn parse_classifier(value: &str) -> Result<Classifier, String> {
+    value.parse().map_err(|e: LumosError| e.to_string())
+}
+
+/// Parse a lightness threshold, between 0 and 1.
+pub fn parse_threshold(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+        Ok(_) => Err(format!(
+            "invalid threshold {value:?}: expected a number between 0 and 1"
+        )),
+        Err(e) => Err(format!("invalid threshold {value:?}: {e}")),
+    }
+}
+
+/// Parse the width of a lightness band (`what`), between 0 and 0.5.
+pub fn parse_band(what: &str, value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(band) if (0.0..=0.5).contains(&band) => Ok(band),
+        Ok(_) => Err(format!(
+            "invalid {what} {value:?}: expected a number between 0 and 0.5"
+        )),
+        Err(e) => Err(format!("invalid {what} {value:?}: {e}")),
+    }
+}
+
+/// Parse the color behind translucent backgrounds.
+pub fn parse_backdrop(value: &str) -> Result<Color, String> {
+    parse_rgb(value).map_err(|e| format!("invalid backdrop: {e}"))
+}
+
+/// Parse a background classification name.
+fn parse_background(value: &str) -> Result<Background, String> {
+    value.parse().map_err(|e: LumosError| e.to_string())
+}
+
+/// Parse a palette size, defaulting to the 16 ANSI colors.
+fn parse_palette_size(value: Option<&str>) -> Result<u16, String> {
+    match value {
+        None | Some("16") => Ok(16),
+        Some("256") => Ok(256),
+        Some(value) => Err(format!(
+            "invalid palette size {value:?}: expected 16 or 256"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(ToString::to_string))
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args, Args::new(Command::Detect));
+        assert_eq!(parse(&["detect"]).unwrap(), args);
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse(&["query", "bg"]).unwrap().command,
+            Command::Query(Query::Background)
+        );
+        assert_eq!(
+            parse(&["query", "palette", "256"]).unwrap().command,
+            Command::Query(Query::Palette(256))
+        );
+        assert_eq!(
+            parse(&["--palette"]).unwrap().command,
+            Command::Query(Query::Palette(16))
+        );
+        assert_eq!(
+            parse(&["classify", "#ffffff"]).unwrap().command,
+            Command::Classify(vec!["#ffffff".to_string()])
+        );
+        assert_eq!(
+            parse(&["classify", "#ffffff", "#000000"]).unwrap().command,
+            Command::Classify(vec!["#ffffff".to_string(), "#000000".to_string()])
+        );
+        assert_eq!(
+            parse(&["classify"]).unwrap().command,
+            Command::Classify(Vec::new())
+        );
+        assert_eq!(
+            parse(&["classify", "-"]).unwrap().command,
+            Command::Classify(Vec::new())
+        );
+        assert_eq!(
+            parse(&["contrast", "#000", "white"]).unwrap().command,
+            Command::Contrast(Some(("#000".to_string(), "white".to_string())))
+        );
+        assert_eq!(
+            parse(&["contrast"]).unwrap().command,
+            Command::Contrast(None)
+        );
+        assert_eq!(parse(&["watch"]).unwrap().command, Command::Watch);
+        assert_eq!(parse(&["invalidate"]).unwrap().command, Command::Invalidate);
+        assert_eq!(parse(&["detect", "--help"]).unwrap().command, Command::Help);
+        assert_eq!(parse(&["-V"]).unwrap().command, Command::Version);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args = parse(&[
+            "--timeout=200",
+            "detect",
+            "--threshold",
+            "0.3",
+            "--format",
+            "json",
+        ])
+        .unwrap();
+        assert_eq!(args.timeout, Duration::from_millis(200));
+        assert_eq!(args.threshold, Some(0.3));
+        assert_eq!(args.classifier, None);
+        assert_eq!(args.format, Format::Json);
+
+        let args = parse(&["--hysteresis", "0.05", "--previous=light"]).unwrap();
+        assert_eq!(args.hysteresis, Some(0.05));
+        assert_eq!(args.previous, Some(Background::Light));
+        assert_eq!(
+            parse(&["--medium-band=0.1"]).unwrap().medium_band,
+            Some(0.1)
+        );
+
+        let args = parse(&["classify", "--classifier=oklab", "#808080"]).unwrap();
+        assert_eq!(args.classifier, Some(Classifier::OkLab));
+
+        assert_eq!(
+            parse(&["--backdrop", "white"]).unwrap().backdrop,
+            Some(Color::from_rgb8(255, 255, 255))
+        );
+
+        let args = parse(&["classify", "--expect=light", "#ffffff"]).unwrap();
+        assert_eq!(args.expect, Some(Background::Light));
+
+        assert_eq!(parse(&["--cache"]).unwrap().cache, Some(DEFAULT_CACHE_TTL));
+        assert_eq!(
+            parse(&["detect", "--cache=500"]).unwrap().cache,
+            Some(Duration::from_millis(500))
+        );
+
+        assert_eq!(parse(&["--json"]).unwrap().format, Format::Json);
+        assert_eq!(
+            parse(&["--export"]).unwrap().format,
+            Format::Export(Shell::Sh)
+        );
+        assert_eq!(
+            parse(&["--format=fish"]).unwrap().format,
+            Format::Export(Shell::Fish)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["--timeout"]).is_err());
+        assert!(parse(&["--timeout", "soon"]).is_err());
+        assert!(parse(&["--threshold", "2"]).is_err());
+        assert!(parse(&["--classifier", "hsl"]).is_err());
+        assert!(parse(&["--hysteresis", "0.6"]).is_err());
+        assert!(parse(&["--medium-band", "wide"]).is_err());
+        assert!(parse(&["--previous", "grey"]).is_err());
+        assert!(parse(&["--backdrop", "#12"]).is_err());
+        assert!(parse(&["--format", "xml"]).is_err());
+        assert!(parse(&["--palette=8"]).is_err());
+        assert!(parse(&["--palette", "watch"]).is_err());
+        assert!(parse(&["--export", "classify", "#fff"]).is_err());
+        assert!(parse(&["--expect", "dim", "classify"]).is_err());
+        assert!(parse(&["--expect", "dark"]).is_err());
+        assert!(parse(&["--cache=soon"]).is_err());
+        assert!(parse(&["watch", "--cache"]).is_err());
+        assert!(parse(&["invalidate", "now"]).is_err());
+        assert!(parse(&["query"]).is_err());
+        assert!(parse(&["contrast", "#000"]).is_err());
+        assert!(parse(&["detect", "now"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+}
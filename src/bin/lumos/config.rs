@@ -0,0 +1,184 @@
+//! Settings from the configuration file and the environment.
+//!
+//! The configuration file is `$XDG_CONFIG_HOME/lumos/config` (or
+//! `~/.config/lumos/config`), with one `key = value` setting per line:
+//!
+//! ```text
+//! # Measure perceived lightness, and consider a bit brighter backgrounds as dark
+//! classifier = oklab
+//! threshold = 0.6
+//! # Classify backgrounds between 0.5 and 0.7 as medium
+//! medium_band = 0.1
+//! hysteresis = 0.05
+//! # The terminal is translucent, over a light wallpaper
+//! backdrop = #e0e0e0
+//! ```
+//!
+//! Environment variables (`LUMOS_CLASSIFIER`, `LUMOS_THRESHOLD`,
+//! `LUMOS_MEDIUM_BAND`, `LUMOS_HYSTERESIS`, `LUMOS_BACKDROP`) override the
+//! file, and command-line options override both.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use lumos::{Classifier, Color};
+
+use crate::cli::{parse_backdrop, parse_band, parse_classifier, parse_threshold};
+
+/// Settings that can be set outside of the command line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// How to measure lightness.
+    pub classifier: Option<Classifier>,
+    /// Lightness below which a color is dark.
+    pub threshold: Option<f64>,
+    /// Half width of the band around the threshold classified as medium.
+    pub medium_band: Option<f64>,
+    /// Distance to the previous classification keeping it.
+    pub hysteresis: Option<f64>,
+    /// Color behind translucent backgrounds.
+    pub backdrop: Option<Color>,
+}
+
+impl Config {
+    /// Load the configuration file, if any, then apply environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the file cannot be read or parsed, or if an
+    /// environment variable holds an invalid value.
+    pub fn load() -> Result<Self, String> {
+        let mut config = match path() {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(text) => parse_config(&text).map_err(|e| format!("{}: {e}", path.display()))?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+                Err(e) => return Err(format!("{}: {e}", path.display())),
+            },
+            None => Self::default(),
+        };
+
+        config.apply_env(|name| env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Override settings with environment variables, read with `var`.
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(value) = var("LUMOS_CLASSIFIER") {
+            self.classifier =
+                Some(parse_classifier(&value).map_err(|e| format!("LUMOS_CLASSIFIER: {e}"))?);
+        }
+        if let Some(value) = var("LUMOS_THRESHOLD") {
+            self.threshold =
+                Some(parse_threshold(&value).map_err(|e| format!("LUMOS_THRESHOLD: {e}"))?);
+        }
+        if let Some(value) = var("LUMOS_MEDIUM_BAND") {
+            self.medium_band = Some(
+                parse_band("medium band", &value).map_err(|e| format!("LUMOS_MEDIUM_BAND: {e}"))?,
+            );
+        }
+        if let Some(value) = var("LUMOS_HYSTERESIS") {
+            self.hysteresis = Some(
+                parse_band("hysteresis", &value).map_err(|e| format!("LUMOS_HYSTERESIS: {e}"))?,
+            );
+        }
+        if let Some(value) = var("LUMOS_BACKDROP") {
+            self.backdrop =
+                Some(parse_backdrop(&value).map_err(|e| format!("LUMOS_BACKDROP: {e}"))?);
+        }
+        Ok(())
+    }
+}
+
+/// Path of the configuration file, if a configuration directory is known.
+fn path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("lumos").join("config"))
+}
+
+/// Parse the content of a configuration file.
+///
+/// Blank lines and lines starting with `#` are ignored.
+fn parse_config(text: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |e: String| format!("line {}: {e}", number + 1);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected key = value, got {line:?}")))?;
+        let value = value.trim();
+
+        match key.trim() {
+            "classifier" => config.classifier = Some(parse_classifier(value).map_err(error)?),
+            "threshold" => config.threshold = Some(parse_threshold(value).map_err(error)?),
+            "medium_band" => {
+                config.medium_band = Some(parse_band("medium band", value).map_err(error)?);
+            }
+            "hysteresis" => {
+                config.hysteresis = Some(parse_band("hysteresis", value).map_err(error)?);
+            }
+            "backdrop" => config.backdrop = Some(parse_backdrop(value).map_err(error)?),
+            key => return Err(error(format!("unknown setting {key:?}"))),
+        }
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config =
+            parse_config("# comment\n\nclassifier = oklab\nthreshold = 0.4\nmedium_band = 0.1\n  hysteresis=0.05  \nbackdrop = #e0e0e0\n")
+                .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                classifier: Some(Classifier::OkLab),
+                threshold: Some(0.4),
+                medium_band: Some(0.1),
+                hysteresis: Some(0.05),
+                backdrop: Some(Color::from_rgb8(0xe0, 0xe0, 0xe0)),
+            }
+        );
+        assert_eq!(parse_config("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        assert_eq!(
+            parse_config("threshold = 0.4\ncolour = red").unwrap_err(),
+            r#"line 2: unknown setting "colour""#
+        );
+        assert!(parse_config("threshold 0.4").is_err());
+        assert!(parse_config("threshold = 1.5").is_err());
+        assert!(parse_config("hysteresis = -1").is_err());
+        assert!(parse_config("classifier = hsl").is_err());
+        assert!(parse_config("backdrop = nope").is_err());
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let mut config = parse_config("threshold = 0.4\nhysteresis = 0.05").unwrap();
+        config
+            .apply_env(|name| (name == "LUMOS_THRESHOLD").then(|| "0.3".to_string()))
+            .unwrap();
+        assert_eq!(config.threshold, Some(0.3));
+        assert_eq!(config.hysteresis, Some(0.05));
+
+        assert!(config.apply_env(|_| Some("high".to_string())).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Shell assignments for `lumos --export`.
+//!
+//! The output is meant to be evaluated by shell rc files, e.g.
+//! `eval "$(lumos --export)"`, so values are always quoted and a failed
+//! detection still produces valid syntax.
+
+use lumos::{Background, Detection};
+
+/// Shell syntax of the exported assignments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// POSIX shells (sh, bash, zsh...).
+    Sh,
+    /// fish.
+    Fish,
+    /// Nushell.
+    Nu,
+}
+
+impl Shell {
+    /// Parse a shell name as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sh" | "bash" | "zsh" => Some(Self::Sh),
+            "fish" => Some(Self::Fish),
+            "nu" | "nushell" => Some(Self::Nu),
+            _ => None,
+        }
+    }
+
+    /// Quote `value` so that the shell reads it back verbatim.
+    fn quote(self, value: &str) -> String {
+        match self {
+            Self::Sh => format!("'{}'", value.replace('\'', r"'\''")),
+            Self::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+            Self::Nu => format!("\"{}\"", value.replace('\\', r"\\").replace('"', "\\\"")),
+        }
+    }
+
+    /// Format a single assignment, including the trailing newline.
+    fn assignment(self, name: &str, value: &str) -> String {
+        let value = self.quote(value);
+        match self {
+            Self::Sh => format!("export {name}={value};\n"),
+            Self::Fish => format!("set -gx {name} {value};\n"),
+            Self::Nu => format!("$env.{name} = {value}\n"),
+        }
+    }
+}
+
+/// Environment variables describing the detected background.
+///
+/// - `LUMOS_BACKGROUND`: `dark`, `medium`, `light` or `unknown`
+/// - `LUMOS_BG`: background color, as `#rrggbb`
+/// - `COLORFGBG`: rxvt-style `<fg>;<bg>` palette indices, read by Vim and
+///   many other programs
+/// - `BAT_THEME`: a bat theme matching the background
+///
+/// Only `LUMOS_BACKGROUND` is set when the detection failed, so that
+/// downstream tools keep their own defaults. Likewise, `COLORFGBG` and
+/// `BAT_THEME` are not set for medium backgrounds, which suit neither dark
+/// nor light themes.
+fn variables(detection: Option<&Detection>) -> Vec<(&'static str, String)> {
+    let Some(detection) = detection else {
+        return vec![("LUMOS_BACKGROUND", "unknown".to_string())];
+    };
+
+    let mut variables = vec![
+        ("LUMOS_BACKGROUND", detection.background.to_string()),
+        ("LUMOS_BG", detection.color.to_string()),
+    ];
+
+    let themes = match detection.background {
+        Background::Dark => Some(("15;0", "Monokai Extended")),
+        Background::Light => Some(("0;15", "Monokai Extended Light")),
+        Background::Medium => None,
+    };
+    if let Some((colorfgbg, bat_theme)) = themes {
+        variables.push(("COLORFGBG", colorfgbg.to_string()));
+        variables.push(("BAT_THEME", bat_theme.to_string()));
+    }
+
+    variables
+}
+
+/// Format the shell assignments for a detection result.
+pub fn export(detection: Option<&Detection>, shell: Shell) -> String {
+    variables(detection)
+        .into_iter()
+        .map(|(name, value)| shell.assignment(name, &value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumos::{Color, Method, Strategy};
+    use std::time::Duration;
+
+    fn detection(background: Background, color: Color) -> Detection {
+        Detection {
+            background,
+            luminance: color.luminance(),
+            lightness: color.luminance(),
+            reply: format!("{color:#}"),
+            color,
+            alpha: 1.0,
+            foreground: None,
+            method: Method::Query(Strategy::Direct),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_shell_from_name() {
+        assert_eq!(Shell::from_name("sh"), Some(Shell::Sh));
+        assert_eq!(Shell::from_name("zsh"), Some(Shell::Sh));
+        assert_eq!(Shell::from_name("fish"), Some(Shell::Fish));
+        assert_eq!(Shell::from_name("nu"), Some(Shell::Nu));
+        assert_eq!(Shell::from_name("csh"), None);
+    }
+
+    #[test]
+    fn test_export_dark() {
+        let dark = detection(Background::Dark, Color::from_rgb8(0x1e, 0x1e, 0x2e));
+        assert_eq!(
+            export(Some(&dark), Shell::Sh),
+            "export LUMOS_BACKGROUND='dark';\n\
+             export LUMOS_BG='#1e1e2e';\n\
+             export COLORFGBG='15;0';\n\
+             export BAT_THEME='Monokai Extended';\n"
+        );
+    }
+
+    #[test]
+    fn test_export_light() {
+        let light = detection(Background::Light, Color::from_rgb8(0xff, 0xff, 0xff));
+        assert_eq!(
+            export(Some(&light), Shell::Fish),
+            "set -gx LUMOS_BACKGROUND 'light';\n\
+             set -gx LUMOS_BG '#ffffff';\n\
+             set -gx COLORFGBG '0;15';\n\
+             set -gx BAT_THEME 'Monokai Extended Light';\n"
+        );
+    }
+
+    #[test]
+    fn test_export_medium() {
+        let medium = detection(Background::Medium, Color::from_rgb8(0x80, 0x80, 0x80));
+        assert_eq!(
+            export(Some(&medium), Shell::Nu),
+            "$env.LUMOS_BACKGROUND = \"medium\"\n\
+             $env.LUMOS_BG = \"#808080\"\n"
+        );
+    }
+
+    #[test]
+    fn test_export_unknown() {
+        assert_eq!(
+            export(None, Shell::Sh),
+            "export LUMOS_BACKGROUND='unknown';\n"
+        );
+        assert_eq!(
+            export(None, Shell::Nu),
+            "$env.LUMOS_BACKGROUND = \"unknown\"\n"
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(Shell::Sh.quote("it's"), r"'it'\''s'");
+        assert_eq!(Shell::Fish.quote(r"it's \o/"), r"'it\'s \\o/'");
+        assert_eq!(Shell::Nu.quote(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+}
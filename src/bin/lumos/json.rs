@@ -0,0 +1,140 @@
+//! Minimal JSON writer.
+//!
+//! The output of `lumos --json` is small and flat, so a few lines of code are
+//! enough to produce it without pulling a serialization framework.
+
+use std::fmt::{self, Write};
+
+/// A JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    /// `null`
+    Null,
+    /// A number, written as `null` when not finite.
+    Number(f64),
+    /// A string, escaped when written.
+    String(String),
+    /// An array of values.
+    Array(Vec<Json>),
+    /// An object, keeping the keys in insertion order.
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    /// Build a string value, or `null` when `value` is `None`.
+    pub fn optional_string(value: Option<impl Into<String>>) -> Self {
+        value.map_or(Self::Null, |value| Self::String(value.into()))
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<u16> for Json {
+    fn from(value: u16) -> Self {
+        Self::Number(f64::from(value))
+    }
+}
+
+/// Write `s` as a quoted JSON string, escaping quotes, backslashes and
+/// control characters.
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(value) if value.is_finite() => write!(f, "{value}"),
+            Self::Null | Self::Number(_) => f.write_str("null"),
+            Self::String(value) => write_string(f, value),
+            Self::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_char(']')
+            }
+            Self::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_scalars() {
+        assert_eq!(Json::Null.to_string(), "null");
+        assert_eq!(Json::from(0.5).to_string(), "0.5");
+        assert_eq!(Json::from(65535u16).to_string(), "65535");
+        assert_eq!(Json::from(f64::NAN).to_string(), "null");
+        assert_eq!(Json::optional_string(None::<&str>).to_string(), "null");
+    }
+
+    #[test]
+    fn test_json_string_escaping() {
+        assert_eq!(Json::from("dark").to_string(), r#""dark""#);
+        assert_eq!(
+            Json::from("a \"b\" \\ c\n\x1b").to_string(),
+            r#""a \"b\" \\ c\n\u001b""#
+        );
+    }
+
+    #[test]
+    fn test_json_nested() {
+        let value = Json::Object(vec![
+            ("background", "dark".into()),
+            (
+                "multiplexers",
+                Json::Array(vec!["tmux".into(), "screen".into()]),
+            ),
+            ("error", Json::Null),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"background":"dark","multiplexers":["tmux","screen"],"error":null}"#
+        );
+    }
+}
@@ -0,0 +1,628 @@
+//! Terminal background color detection utility.
+//!
+//! This program queries the terminal for its background color using OSC 11 escape sequences,
+//! and determines whether it's a dark or light theme based on the relative luminance.
+//!
+//! It can also classify colors given on the command line, without any terminal.
+
+mod cli;
+mod config;
+mod export;
+mod json;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use lumos::{
+    Background, Color, DEFAULT_THRESHOLD, Detection, Detector, LumosError, apca_contrast,
+    contrast_ratio, debug, detect_multiplexers, invalidate_cache, parse_rgb, parse_rgba,
+    query_colors, query_palette,
+};
+
+use cli::{Args, Command, Format, HELP, Query, USAGE, parse_args};
+use config::Config;
+use export::export;
+use json::Json;
+
+/// Map a detection error to the process exit code.
+///
+/// Scripts can tell apart running without a terminal, running in a terminal
+/// not supporting color queries, and other failures.
+fn exit_code(e: &LumosError) -> i32 {
+    match e {
+        LumosError::NoTerminal(_) | LumosError::TerminalSetup(_) => 3,
+        LumosError::Unsupported | LumosError::NoReply => 4,
+        LumosError::Timeout(_) => 5,
+        LumosError::InvalidUtf8(_) | LumosError::InvalidColor { .. } => 6,
+        LumosError::Io(_)
+        | LumosError::InvalidBackground(_)
+        | LumosError::InvalidClassifier(_)
+        | LumosError::Cache(_) => 2,
+    }
+}
+
+/// Build the detector from the command line, falling back to the
+/// configuration for unset options.
+fn detector(args: &Args, config: &Config) -> Detector {
+    Detector::new()
+        .timeout(args.timeout)
+        .classifier(args.classifier.or(config.classifier).unwrap_or_default())
+        .threshold(
+            args.threshold
+                .or(config.threshold)
+                .unwrap_or(DEFAULT_THRESHOLD),
+        )
+        .medium_band(args.medium_band.or(config.medium_band).unwrap_or(0.0))
+        .hysteresis(args.hysteresis.or(config.hysteresis).unwrap_or(0.0))
+        .previous(args.previous)
+        .backdrop(
+            args.backdrop
+                .clone()
+                .or_else(|| config.backdrop.clone())
+                .unwrap_or(Color::new(0, 0, 0)),
+        )
+}
+
+/// Run `command` with the detector, loading the configuration first.
+///
+/// # Returns
+///
+/// The exit code of `command`, or `1` if the configuration is invalid.
+fn with_detector(args: &Args, command: impl FnOnce(&Detector) -> i32) -> i32 {
+    match Config::load() {
+        Ok(config) => command(&detector(args, &config)),
+        Err(e) => {
+            eprintln!("lumos: {e}");
+            1
+        }
+    }
+}
+
+/// Write to stdout, flushing it.
+///
+/// # Returns
+///
+/// `Err` with the exit code to stop with if writing fails: 0 when stdout was
+/// closed by its reader (e.g. `lumos --help | head -3`), the output being
+/// cut short on purpose, or 2 otherwise.
+fn output(write: impl FnOnce(&mut io::StdoutLock) -> io::Result<()>) -> Result<(), i32> {
+    let mut out = io::stdout().lock();
+    match write(&mut out).and_then(|()| out.flush()) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Err(0),
+        Err(e) => {
+            eprintln!("lumos: failed to write to stdout: {e}");
+            Err(2)
+        }
+    }
+}
+
+/// Detect the background, logging the outcome.
+///
+/// With a `cache` duration, a recent detection of the terminal session is
+/// reused.
+fn detect(detector: &Detector, cache: Option<Duration>) -> Result<Detection, LumosError> {
+    let result = match cache {
+        Some(ttl) => detector.detect_cached(ttl),
+        None => detector.detect(),
+    };
+
+    match &result {
+        Ok(detection) => debug!(
+            "method={} elapsed={:?}",
+            detection.method, detection.elapsed
+        ),
+        Err(e) => {
+            debug!("Error: {e}");
+            debug!("unable to determine background color");
+        }
+    }
+
+    result
+}
+
+/// Print the background classification.
+///
+/// The text output has no trailing newline, so that shells and editors can
+/// use it as is.
+fn print_detection(detector: &Detector, args: &Args) -> i32 {
+    let result = detect(detector, args.cache);
+
+    let written = output(|out| match args.format {
+        Format::Text => match &result {
+            Ok(detection) => write!(out, "{}", detection.background),
+            Err(_) => write!(out, "unknown"),
+        },
+        Format::Json => writeln!(out, "{}", detection_json(&result)),
+        Format::Export(shell) => write!(out, "{}", export(result.as_ref().ok(), shell)),
+    });
+    if let Err(code) = written {
+        return code;
+    }
+
+    result.as_ref().map_or_else(exit_code, |_| 0)
+}
+
+/// Print the background classification each time it changes, until killed.
+///
+/// Each classification is the previous one of the next detection, so that
+/// the hysteresis band avoids flips. Failing detections are reported as
+/// `unknown`, and only stop the loop when not running in a terminal at all.
+fn watch(detector: &Detector, args: &Args) -> i32 {
+    let mut detector = detector.clone();
+    let mut last = None;
+
+    loop {
+        let result = detect(&detector, None);
+        let background = result.as_ref().ok().map(|detection| detection.background);
+
+        if last != Some(background) {
+            let written = output(|out| match args.format {
+                Format::Json => writeln!(out, "{}", detection_json(&result)),
+                _ => writeln!(out, "{}", background.map_or("unknown", |bg| bg.as_str())),
+            });
+            if let Err(code) = written {
+                return code;
+            }
+            last = Some(background);
+        }
+        if background.is_some() {
+            detector = detector.previous(background);
+        }
+
+        if let Err(e @ (LumosError::NoTerminal(_) | LumosError::TerminalSetup(_))) = &result {
+            return exit_code(e);
+        }
+
+        thread::sleep(args.interval);
+    }
+}
+
+/// Remove the cached detection of the terminal session.
+fn invalidate() -> i32 {
+    match invalidate_cache() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("lumos: {e}");
+            exit_code(&e)
+        }
+    }
+}
+
+/// Print the terminal foreground or background color.
+fn print_color(query: Query, args: &Args) -> i32 {
+    let colors = match query_colors(args.timeout) {
+        Ok(colors) => colors,
+        Err(e) => {
+            debug!("Error: {e}");
+            return print_optional_color(None, args.format)
+                .map_or_else(|code| code, |()| exit_code(&e));
+        }
+    };
+
+    let color = match query {
+        Query::Foreground => colors.foreground,
+        _ => colors.background,
+    };
+    if let Err(code) = print_optional_color(color.as_ref(), args.format) {
+        return code;
+    }
+
+    color.map_or_else(|| exit_code(&LumosError::Unsupported), |_| 0)
+}
+
+/// Print a single color, or `unknown`, see [`output`].
+fn print_optional_color(color: Option<&Color>, format: Format) -> Result<(), i32> {
+    output(|out| match format {
+        Format::Json => writeln!(out, "{}", color.map_or(Json::Null, color_json)),
+        _ => writeln!(
+            out,
+            "{}",
+            color.map_or_else(|| "unknown".to_string(), Color::to_string)
+        ),
+    })
+}
+
+/// Query palette entries and print one `<index> <color>` line per entry.
+///
+/// Entries the terminal did not report are printed as `unknown`, or `null`
+/// in the JSON array.
+///
+/// # Returns
+///
+/// The process exit code.
+fn print_palette(size: u16, args: &Args) -> i32 {
+    let last = u8::try_from(size - 1).unwrap_or(u8::MAX);
+    match query_palette(0..=last, args.timeout) {
+        Ok(colors) => {
+            let written = output(|out| match args.format {
+                Format::Json => writeln!(
+                    out,
+                    "{}",
+                    Json::Array(
+                        colors
+                            .iter()
+                            .map(|color| color.as_ref().map_or(Json::Null, color_json))
+                            .collect()
+                    )
+                ),
+                _ => colors
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(index, color)| match color {
+                        Some(color) => writeln!(out, "{index} {color}"),
+                        None => writeln!(out, "{index} unknown"),
+                    }),
+            });
+            written.err().unwrap_or(0)
+        }
+        Err(e) => {
+            debug!("Error: {e}");
+            debug!("unable to determine palette colors");
+            exit_code(&e)
+        }
+    }
+}
+
+/// Classify a color as dark or light, along with the line to print.
+///
+/// Translucent colors are composited over the backdrop first. With
+/// `labeled`, text lines are prefixed with the color, so that the output of
+/// several colors can be told apart.
+fn classify_color(
+    input: &str,
+    labeled: bool,
+    detector: &Detector,
+    args: &Args,
+) -> Result<(Background, String), LumosError> {
+    let (color, alpha) = parse_rgba(input)?;
+    let color = detector.composite(&color, alpha);
+    let luminance = color.luminance();
+    let lightness = detector.lightness(&color);
+    let background = detector.classify(lightness);
+
+    let line = match args.format {
+        Format::Json => Json::Object(vec![
+            ("input", input.into()),
+            ("color", color_json(&color)),
+            ("alpha", alpha.into()),
+            ("background", background.as_str().into()),
+            ("luminance", luminance.into()),
+            ("lightness", lightness.into()),
+        ])
+        .to_string(),
+        _ if labeled => format!("{input} {background}"),
+        _ => background.to_string(),
+    };
+
+    Ok((background, line))
+}
+
+/// Classify colors given on the command line, or read from stdin (one per
+/// line, blank lines being ignored) when there are none.
+///
+/// Every color is classified even if some fail, so that all problems are
+/// reported at once.
+///
+/// # Returns
+///
+/// The process exit code of the first failure, `6` for an invalid color, or
+/// `7` for a color not matching `--expect`.
+fn classify(colors: &[String], detector: &Detector, args: &Args) -> i32 {
+    let inputs: Box<dyn Iterator<Item = io::Result<String>>> = if colors.is_empty() {
+        Box::new(io::stdin().lines())
+    } else {
+        Box::new(colors.iter().cloned().map(Ok))
+    };
+    let labeled = colors.len() != 1;
+    let mut code = 0;
+
+    for input in inputs {
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("lumos: failed to read stdin: {e}");
+                return 2;
+            }
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        let failure = match classify_color(input, labeled, detector, args) {
+            Ok((background, line)) => match output(|out| writeln!(out, "{line}")) {
+                // Output was cut short, but earlier failures still count
+                Err(0) => return code,
+                Err(failure) => return failure,
+                Ok(()) => match args.expect {
+                    Some(expected) if expected != background => {
+                        eprintln!("lumos: {input} is {background}, expected {expected}");
+                        7
+                    }
+                    _ => 0,
+                },
+            },
+            Err(e) => {
+                eprintln!("lumos: {e}");
+                exit_code(&e)
+            }
+        };
+        if code == 0 {
+            code = failure;
+        }
+    }
+
+    code
+}
+
+/// WCAG 2 conformance level of a contrast ratio, for normal text unless
+/// stated otherwise.
+fn wcag_level(ratio: f64) -> &'static str {
+    if ratio >= 7.0 {
+        "AAA"
+    } else if ratio >= 4.5 {
+        "AA"
+    } else if ratio >= 3.0 {
+        "AA large text"
+    } else {
+        "fail"
+    }
+}
+
+/// Print the WCAG contrast ratio and APCA lightness contrast of foreground
+/// text over a background.
+///
+/// Colors are given on the command line, or queried from the terminal when
+/// there are none.
+fn contrast(colors: Option<&(String, String)>, args: &Args) -> i32 {
+    let colors = match colors {
+        Some((fg, bg)) => parse_rgb(fg).and_then(|fg| Ok((fg, parse_rgb(bg)?))),
+        None => query_colors(args.timeout).and_then(|colors| {
+            colors
+                .foreground
+                .zip(colors.background)
+                .ok_or(LumosError::Unsupported)
+        }),
+    };
+    let (fg, bg) = match colors {
+        Ok(colors) => colors,
+        Err(e) => {
+            eprintln!("lumos: {e}");
+            return exit_code(&e);
+        }
+    };
+
+    let ratio = contrast_ratio(&fg, &bg);
+    let lc = apca_contrast(&fg, &bg);
+
+    let written = output(|out| {
+        if args.format == Format::Json {
+            let json = Json::Object(vec![
+                ("foreground", color_json(&fg)),
+                ("background", color_json(&bg)),
+                ("contrast_ratio", ratio.into()),
+                ("wcag_level", wcag_level(ratio).into()),
+                ("apca_lc", lc.into()),
+            ]);
+            writeln!(out, "{json}")
+        } else {
+            writeln!(
+                out,
+                "WCAG 2 contrast ratio: {ratio:.2}:1 ({})",
+                wcag_level(ratio)
+            )?;
+            writeln!(out, "APCA lightness contrast: Lc {lc:.1}")
+        }
+    });
+
+    written.err().unwrap_or(0)
+}
+
+/// Describe a color as a JSON object, in hex and 16-bit forms.
+fn color_json(color: &Color) -> Json {
+    Json::Object(vec![
+        ("hex", color.to_string().into()),
+        ("rgb", format!("{color:#}").into()),
+        ("red", color.red().into()),
+        ("green", color.green().into()),
+        ("blue", color.blue().into()),
+    ])
+}
+
+/// Describe a detection result as a JSON object.
+///
+/// Failed detections keep the same fields, with `unknown` as background,
+/// `null` for everything that could not be determined, and the error message.
+fn detection_json(result: &Result<Detection, LumosError>) -> Json {
+    let terminal = Json::Object(vec![
+        ("term", Json::optional_string(env::var("TERM").ok())),
+        (
+            "program",
+            Json::optional_string(env::var("TERM_PROGRAM").ok()),
+        ),
+    ]);
+    let multiplexers = Json::Array(
+        detect_multiplexers()
+            .iter()
+            .map(|mux| mux.to_string().into())
+            .collect(),
+    );
+
+    match result {
+        Ok(detection) => Json::Object(vec![
+            ("background", detection.background.as_str().into()),
+            ("background_color", color_json(&detection.color)),
+            ("alpha", detection.alpha.into()),
+            (
+                "foreground_color",
+                detection.foreground.as_ref().map_or(Json::Null, color_json),
+            ),
+            ("luminance", detection.luminance.into()),
+            ("lightness", detection.lightness.into()),
+            (
+                "contrast_ratio",
+                detection
+                    .foreground
+                    .as_ref()
+                    .map_or(Json::Null, |fg| contrast_ratio(fg, &detection.color).into()),
+            ),
+            (
+                "apca_lc",
+                detection
+                    .foreground
+                    .as_ref()
+                    .map_or(Json::Null, |fg| apca_contrast(fg, &detection.color).into()),
+            ),
+            ("terminal", terminal),
+            ("multiplexers", multiplexers),
+            ("method", detection.method.to_string().into()),
+            (
+                "elapsed_ms",
+                (detection.elapsed.as_secs_f64() * 1000.0).into(),
+            ),
+            ("error", Json::Null),
+        ]),
+        Err(e) => Json::Object(vec![
+            ("background", "unknown".into()),
+            ("background_color", Json::Null),
+            ("alpha", Json::Null),
+            ("foreground_color", Json::Null),
+            ("luminance", Json::Null),
+            ("lightness", Json::Null),
+            ("contrast_ratio", Json::Null),
+            ("apca_lc", Json::Null),
+            ("terminal", terminal),
+            ("multiplexers", multiplexers),
+            ("method", Json::Null),
+            ("elapsed_ms", Json::Null),
+            ("error", e.to_string().into()),
+        ]),
+    }
+}
+
+/// Main entry point for the lumos terminal background color detection utility.
+///
+/// # Commands
+///
+/// - `detect` (default): Print whether the terminal background is dark or
+///   light.
+/// - `query fg|bg`: Print the terminal foreground or background color.
+/// - `query palette [16|256]`: Print the terminal palette, as one
+///   `<index> #rrggbb` line per entry (default: 16 entries).
+/// - `classify [COLOR]...`: Print whether colors are dark or light, without
+///   querying the terminal. Colors are read from stdin, one per line, when
+///   none is given (or `-`), and each output line is then prefixed with its
+///   color.
+/// - `contrast [FG BG]`: Print the WCAG 2 contrast ratio (with its
+///   conformance level) and the APCA lightness contrast of foreground text
+///   over a background. Without colors, the terminal is queried for its
+///   own.
+/// - `watch`: Detect the background every `--interval`, printing it on its
+///   own line each time it changes.
+/// - `invalidate`: Forget the cached detection of the terminal session, see
+///   `--cache`.
+///
+/// # Options
+///
+/// - `--timeout <MS>`: Give up if the terminal does not answer within `MS`
+///   milliseconds (default: 1000).
+/// - `--format <FORMAT>`: `text` (default), `json`, or a shell syntax (`sh`,
+///   `fish` or `nu`) for `detect`.
+/// - `--classifier <NAME>`: How to measure the lightness of colors:
+///   `luminance` (WCAG relative luminance, default), `cielab` (CIELAB `L*`)
+///   or `oklab` (`OKLab` `L`).
+/// - `--threshold <LUM>`: Lightness below which a color is dark (default:
+///   0.5).
+/// - `--medium-band <LUM>`: Classify colors with a lightness within
+///   `threshold ± LUM` as `medium` instead of dark or light (default: 0).
+/// - `--hysteresis <LUM>`: Keep the previous classification when the
+///   lightness is within `LUM` of the range it covers (default: 0). `watch`
+///   uses its last classification.
+/// - `--previous <dark|medium|light>`: Previous classification, for the
+///   hysteresis.
+/// - `--backdrop <COLOR>`: Color assumed behind translucent backgrounds,
+///   which are composited over it before being classified (default: black).
+/// - `--interval <MS>`: Time between two detections of `watch` (default:
+///   1000).
+/// - `--expect <dark|medium|light>`: Make `classify` fail if any color is not
+///   classified as expected, e.g. to validate themes in CI.
+/// - `--cache[=MS]`: Reuse the detection of the terminal session for `detect`
+///   if it is at most `MS` milliseconds old (default: 60000). Detections are
+///   cached in `$XDG_RUNTIME_DIR/lumos/`, and the cached classification is
+///   the previous one for the hysteresis. Terminals that do not answer are
+///   cached too, so that they only cost one `--timeout` per `MS`.
+/// - `--json`, `--export[=sh|fish|nu]` and `--palette[=16|256]`: Shortcuts
+///   for `--format=json`, `--format=<shell>` and `query palette`.
+/// - `-h`, `--help` and `-V`, `--version`.
+///
+/// # Environment Variables
+///
+/// - `DEBUG`: When set to any value, enables debug output to stderr.
+/// - `COLORFGBG`: rxvt-style `<fg>;<bg>` palette indices, used to classify
+///   the background when the terminal cannot be queried.
+/// - `LUMOS_CLASSIFIER`, `LUMOS_THRESHOLD`, `LUMOS_MEDIUM_BAND`,
+///   `LUMOS_HYSTERESIS` and `LUMOS_BACKDROP`: Defaults of the matching
+///   options, overriding the configuration file.
+/// - `LUMOS_MULTIPLEXERS`: Comma separated multiplexer layers, innermost
+///   first (e.g. `screen,tmux`), overriding their detection.
+///
+/// # Output
+///
+/// `detect` prints to stdout one of:
+/// - `dark` for dark backgrounds
+/// - `light` for light backgrounds
+/// - `medium` for backgrounds in the medium band, if set
+/// - `unknown` when the background cannot be determined
+///
+/// With `--format=json`, it prints a single JSON object instead, with the
+/// classification, colors, background alpha, luminance, contrast ratio,
+/// detected terminal and multiplexers, method and timing, followed by a
+/// newline.
+///
+/// With a shell format, it prints assignments of `LUMOS_BACKGROUND`,
+/// `LUMOS_BG`, `COLORFGBG` and `BAT_THEME`, to be evaluated by the shell.
+/// Only `LUMOS_BACKGROUND` is set, to `unknown`, when the background cannot
+/// be determined.
+///
+/// # Exit Codes
+///
+/// - `0`: Success
+/// - `1`: Invalid command-line arguments or configuration
+/// - `2`: Unable to determine background color (other errors)
+/// - `3`: Not running in a terminal
+/// - `4`: Terminal does not support color queries
+/// - `5`: Terminal did not answer in time
+/// - `6`: Invalid color, from the terminal or the command line
+/// - `7`: A color did not match `--expect`
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("lumos: {e}");
+            eprintln!("{USAGE}");
+            process::exit(1);
+        }
+    };
+
+    let code = match &args.command {
+        Command::Help => output(|out| writeln!(out, "{HELP}")).err().unwrap_or(0),
+        Command::Version => output(|out| writeln!(out, "lumos {}", env!("CARGO_PKG_VERSION")))
+            .err()
+            .unwrap_or(0),
+        Command::Detect => with_detector(&args, |detector| print_detection(detector, &args)),
+        Command::Watch => with_detector(&args, |detector| watch(detector, &args)),
+        Command::Query(Query::Palette(size)) => print_palette(*size, &args),
+        Command::Query(query) => print_color(*query, &args),
+        Command::Classify(colors) => {
+            with_detector(&args, |detector| classify(colors, detector, &args))
+        }
+        Command::Contrast(colors) => contrast(colors.as_ref(), &args),
+        Command::Invalidate => invalidate(),
+    };
+
+    process::exit(code);
+}
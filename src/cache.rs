@@ -0,0 +1,442 @@
+//! On-disk cache of detection results.
+//!
+//! Shell prompts run lumos on every render, and querying the terminal each
+//! time costs a raw mode round-trip (or a timeout with terminals that do not
+//! answer). The cache keeps the last replies of each terminal session, or
+//! how the terminal failed to answer, in `$XDG_RUNTIME_DIR/lumos/`, which is
+//! private to the user and cleared on logout.
+//!
+//! A terminal session is identified by the device of its tty, the variables
+//! identifying the terminal emulator and its window, and the multiplexer pane
+//! if any, hashed into the name of the cache file. Without
+//! `$XDG_RUNTIME_DIR` or a tty, nothing is cached.
+
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, IsTerminal};
+use std::os::fd::AsFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::color::Background;
+use crate::error::LumosError;
+
+/// Environment variables identifying the terminal emulator, its window, and
+/// the multiplexer pane.
+const SESSION_VARIABLES: &[&str] = &[
+    "TERM",
+    "TERM_PROGRAM",
+    "TERM_SESSION_ID",
+    "ITERM_SESSION_ID",
+    "KITTY_WINDOW_ID",
+    "WEZTERM_PANE",
+    "ALACRITTY_WINDOW_ID",
+    "WINDOWID",
+    "TMUX",
+    "TMUX_PANE",
+    "STY",
+    "WINDOW",
+    "ZELLIJ_SESSION_NAME",
+    "ZELLIJ_PANE_ID",
+];
+
+/// A cached detection: the raw terminal replies, or how the terminal failed
+/// to answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    /// When the terminal was queried.
+    pub(crate) time: SystemTime,
+    /// What the terminal answered.
+    pub(crate) replies: Result<Replies, Failure>,
+}
+
+/// Terminal replies, and their classification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Replies {
+    /// Classification of the background, kept as the previous one of the next
+    /// detection.
+    pub(crate) background: Background,
+    /// Background color reply.
+    pub(crate) reply: String,
+    /// Foreground color reply, if the terminal answered it.
+    pub(crate) foreground: Option<String>,
+}
+
+/// Ways the terminal can fail to answer that are worth caching, as asking
+/// again would fail the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Failure {
+    /// See [`LumosError::Timeout`].
+    Timeout(Duration),
+    /// See [`LumosError::Unsupported`].
+    Unsupported,
+    /// See [`LumosError::NoReply`].
+    NoReply,
+}
+
+impl Failure {
+    /// The failure behind `e`, if worth caching.
+    pub(crate) fn from_error(e: &LumosError) -> Option<Self> {
+        match e {
+            LumosError::Timeout(timeout) => Some(Self::Timeout(*timeout)),
+            LumosError::Unsupported => Some(Self::Unsupported),
+            LumosError::NoReply => Some(Self::NoReply),
+            _ => None,
+        }
+    }
+
+    /// The error this failure was made from.
+    pub(crate) fn to_error(self) -> LumosError {
+        match self {
+            Self::Timeout(timeout) => LumosError::Timeout(timeout),
+            Self::Unsupported => LumosError::Unsupported,
+            Self::NoReply => LumosError::NoReply,
+        }
+    }
+
+    /// Whether the failure still holds for a query waiting for `timeout`: a
+    /// terminal that did not answer in time may answer a longer query.
+    pub(crate) fn holds_for(self, timeout: Duration) -> bool {
+        match self {
+            Self::Timeout(cached) => timeout <= cached,
+            Self::Unsupported | Self::NoReply => true,
+        }
+    }
+
+    /// Parse a failure written by [`Entry::to_text`].
+    fn parse(text: &str) -> Option<Self> {
+        match text.split_once(' ') {
+            Some(("timeout", millis)) => {
+                Some(Self::Timeout(Duration::from_millis(millis.parse().ok()?)))
+            }
+            None if text == "unsupported" => Some(Self::Unsupported),
+            None if text == "no-reply" => Some(Self::NoReply),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout(timeout) => write!(f, "timeout {}", timeout.as_millis()),
+            Self::Unsupported => write!(f, "unsupported"),
+            Self::NoReply => write!(f, "no-reply"),
+        }
+    }
+}
+
+impl Entry {
+    /// Whether the entry is at most `ttl` old, to the millisecond.
+    pub(crate) fn is_fresh(&self, ttl: Duration) -> bool {
+        SystemTime::now()
+            .duration_since(self.time)
+            .is_ok_and(|age| age.as_millis() <= ttl.as_millis())
+    }
+
+    /// Serialize the entry as `key = value` lines, the time being in
+    /// milliseconds since the Unix epoch.
+    fn to_text(&self) -> String {
+        let time = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut text = format!("time = {time}\n");
+        match &self.replies {
+            Ok(replies) => {
+                let _ = writeln!(text, "background = {}", replies.background);
+                let _ = writeln!(text, "reply = {}", replies.reply);
+                if let Some(foreground) = &replies.foreground {
+                    let _ = writeln!(text, "foreground = {foreground}");
+                }
+            }
+            Err(failure) => {
+                let _ = writeln!(text, "error = {failure}");
+            }
+        }
+        text
+    }
+
+    /// Parse an entry written by [`Entry::to_text`].
+    ///
+    /// Returns `None` if anything is missing or invalid, so that broken
+    /// entries are simply replaced.
+    fn parse(text: &str) -> Option<Self> {
+        let (mut time, mut background, mut reply, mut foreground) = (None, None, None, None);
+        let mut error = None;
+
+        for line in text.lines() {
+            let (key, value) = line.split_once(" = ")?;
+            match key {
+                "time" => time = Some(UNIX_EPOCH + Duration::from_millis(value.parse().ok()?)),
+                "background" => background = Some(value.parse().ok()?),
+                "reply" => reply = Some(value.to_string()),
+                "foreground" => foreground = Some(value.to_string()),
+                "error" => error = Some(Failure::parse(value)?),
+                _ => return None,
+            }
+        }
+
+        let replies = match (error, background, reply) {
+            (None, Some(background), Some(reply)) => Ok(Replies {
+                background,
+                reply,
+                foreground,
+            }),
+            (Some(failure), None, None) if foreground.is_none() => Err(failure),
+            _ => return None,
+        };
+        Some(Self {
+            time: time?,
+            replies,
+        })
+    }
+}
+
+/// Path of the cache file of the current terminal session, if it can be
+/// identified and a runtime directory is known.
+pub(crate) fn path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+    let key = session_key(|name| env::var(name).ok(), tty_device()?);
+    Some(Path::new(&dir).join("lumos").join(format!("{key:016x}")))
+}
+
+/// Read the entry at `path`, if there is a valid one.
+pub(crate) fn read(path: &Path) -> Option<Entry> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| Entry::parse(&text))
+}
+
+/// Write `entry` at `path`, creating the private cache directory if needed.
+///
+/// The entry is written to a temporary file renamed over `path`, so that
+/// concurrent readers never see a partial entry.
+pub(crate) fn write(path: &Path, entry: &Entry) -> io::Result<()> {
+    if entry.replies.as_ref().is_ok_and(|replies| {
+        replies.reply.contains('\n')
+            || replies
+                .foreground
+                .as_ref()
+                .is_some_and(|fg| fg.contains('\n'))
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "replies cannot be cached",
+        ));
+    }
+
+    if let Some(dir) = path.parent() {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    let temporary = path.with_extension(format!("{}.tmp", process::id()));
+    fs::write(&temporary, entry.to_text())?;
+    fs::rename(&temporary, path)
+}
+
+/// Remove the entry at `path`, if any.
+pub(crate) fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Device number of the tty we are running in.
+///
+/// `/dev/tty` is the same device for every process, so the tty is looked up
+/// on the standard streams, stderr first as the output of prompts is usually
+/// captured.
+fn tty_device() -> Option<u64> {
+    let (stderr, stdin, stdout) = (io::stderr(), io::stdin(), io::stdout());
+    let fd = [stderr.as_fd(), stdin.as_fd(), stdout.as_fd()]
+        .into_iter()
+        .find(IsTerminal::is_terminal)?;
+    let file = File::from(fd.try_clone_to_owned().ok()?);
+    Some(file.metadata().ok()?.rdev())
+}
+
+/// Hash the tty device and the session variables, read with `var`.
+fn session_key(var: impl Fn(&str) -> Option<String>, tty: u64) -> u64 {
+    let mut key = format!("tty={tty}");
+    for name in SESSION_VARIABLES {
+        if let Some(value) = var(name) {
+            let _ = write!(key, "\0{name}={value}");
+        }
+    }
+    fnv1a(key.as_bytes())
+}
+
+/// 64-bit FNV-1a hash, stable across Rust releases unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            replies: Ok(Replies {
+                background: Background::Dark,
+                reply: "rgb:1e1e/1e1e/2e2e".to_string(),
+                foreground: Some("rgb:cdcd/d6d6/f4f4".to_string()),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_session_key() {
+        let tmux = |pane: &'static str| {
+            move |name: &str| match name {
+                "TERM" => Some("tmux-256color".to_string()),
+                "TMUX_PANE" => Some(pane.to_string()),
+                _ => None,
+            }
+        };
+
+        assert_eq!(
+            session_key(tmux("%1"), 34816),
+            session_key(tmux("%1"), 34816)
+        );
+        assert_ne!(
+            session_key(tmux("%1"), 34816),
+            session_key(tmux("%2"), 34816)
+        );
+        assert_ne!(
+            session_key(tmux("%1"), 34816),
+            session_key(tmux("%1"), 34817)
+        );
+        assert_ne!(session_key(tmux("%1"), 34816), session_key(|_| None, 34816));
+    }
+
+    #[test]
+    fn test_entry_text() {
+        let entry = entry();
+        assert_eq!(
+            entry.to_text(),
+            "time = 1700000000123\nbackground = dark\nreply = rgb:1e1e/1e1e/2e2e\n\
+             foreground = rgb:cdcd/d6d6/f4f4\n"
+        );
+        assert_eq!(Entry::parse(&entry.to_text()), Some(entry.clone()));
+
+        let entry = Entry {
+            replies: entry.replies.map(|replies| Replies {
+                foreground: None,
+                ..replies
+            }),
+            ..entry
+        };
+        assert_eq!(Entry::parse(&entry.to_text()), Some(entry.clone()));
+
+        let failures = [
+            Failure::Timeout(Duration::from_millis(200)),
+            Failure::Unsupported,
+            Failure::NoReply,
+        ];
+        for failure in failures {
+            let entry = Entry {
+                replies: Err(failure),
+                ..entry.clone()
+            };
+            assert_eq!(Entry::parse(&entry.to_text()), Some(entry));
+        }
+        assert_eq!(
+            Entry::parse("time = 0\nerror = timeout 200").map(|entry| entry.replies),
+            Some(Err(Failure::Timeout(Duration::from_millis(200))))
+        );
+
+        assert_eq!(Entry::parse(""), None);
+        assert_eq!(
+            Entry::parse("time = soon\nbackground = dark\nreply = #000"),
+            None
+        );
+        assert_eq!(Entry::parse("background = dark\nreply = #000"), None);
+        assert_eq!(
+            Entry::parse("time = 0\nbackground = dark\nreply = #000\ncolor = red"),
+            None
+        );
+        assert_eq!(Entry::parse("time = 0\nerror = broken"), None);
+        assert_eq!(
+            Entry::parse("time = 0\nerror = unsupported\nbackground = dark\nreply = #000"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_entry_is_fresh() {
+        let entry = Entry {
+            time: SystemTime::now() - Duration::from_secs(10),
+            ..entry()
+        };
+        assert!(entry.is_fresh(Duration::from_mins(1)));
+        assert!(!entry.is_fresh(Duration::from_secs(5)));
+
+        let future = Entry {
+            time: SystemTime::now() + Duration::from_mins(1),
+            ..entry
+        };
+        assert!(!future.is_fresh(Duration::from_mins(1)));
+    }
+
+    #[test]
+    fn test_failure_holds_for() {
+        let timeout = Failure::Timeout(Duration::from_millis(200));
+        assert!(timeout.holds_for(Duration::from_millis(100)));
+        assert!(timeout.holds_for(Duration::from_millis(200)));
+        assert!(!timeout.holds_for(Duration::from_millis(500)));
+
+        assert!(Failure::Unsupported.holds_for(Duration::MAX));
+        assert!(Failure::NoReply.holds_for(Duration::MAX));
+    }
+
+    #[test]
+    fn test_read_write_remove() -> io::Result<()> {
+        let dir = env::temp_dir().join(format!("lumos-cache-test-{}", process::id()));
+        let path = dir.join("lumos").join("0123456789abcdef");
+
+        assert_eq!(read(&path), None);
+        write(&path, &entry())?;
+        assert_eq!(read(&path), Some(entry()));
+        assert_eq!(fs::metadata(path.parent().unwrap())?.mode() & 0o777, 0o700);
+
+        // Short TTLs hit right after a write
+        let fresh = Entry {
+            time: SystemTime::now(),
+            ..entry()
+        };
+        write(&path, &fresh)?;
+        assert!(read(&path).is_some_and(|entry| entry.is_fresh(Duration::from_millis(500))));
+
+        remove(&path)?;
+        assert_eq!(read(&path), None);
+        remove(&path)?;
+
+        let broken = Entry {
+            replies: Ok(Replies {
+                background: Background::Dark,
+                reply: "rgb:0/0/0\nbackground = light".to_string(),
+                foreground: None,
+            }),
+            ..entry()
+        };
+        assert!(write(&path, &broken).is_err());
+
+        fs::remove_dir_all(dir)
+    }
+}
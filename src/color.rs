@@ -1,60 +1,388 @@
 //! Color parsing and luminance calculation utilities.
 //!
 //! This module provides functions for:
-//! - Parsing various color formats (hex, RGB, X11 format)
+//! - Parsing various color formats (hex, X11 specifications, CSS functions, names)
 //! - Converting between color representations
 //! - Calculating relative luminance for accessibility
 //! - Determining if colors are dark or light
 
-use anyhow::{Context, Result, anyhow};
-use regex::Regex;
-use std::sync::LazyLock;
-
-static RGB_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"rgb\((\d+),\s*(\d+),\s*(\d+)\)").expect("Failed to compile RGB regex")
-});
-
-/// Threshold for determining if a color is dark or light based on luminance.
-/// Colors with luminance below this value are considered dark.
-const DARK_THRESHOLD: f64 = 0.5;
-
-/// RGB color representation with red, green, and blue components.
-#[derive(Debug, PartialEq)]
-#[allow(clippy::upper_case_acronyms)]
-pub(crate) struct RGB {
-    /// Red component (0-255)
-    r: u8,
-    /// Green component (0-255)
-    g: u8,
-    /// Blue component (0-255)
-    b: u8,
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{LumosError, Result};
+use crate::{css, names, xcolor};
+
+/// Whether a background is dark or light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Background {
+    /// Dark background, calling for light text.
+    Dark,
+    /// Background in the middle band around the threshold, where neither
+    /// dark nor light color schemes look good. Only reported when a middle
+    /// band is set, see [`classify_lightness`].
+    Medium,
+    /// Light background, calling for dark text.
+    Light,
+}
+
+impl Background {
+    /// Lowercase name of the classification (`"dark"`, `"medium"` or
+    /// `"light"`).
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Medium => "medium",
+            Self::Light => "light",
+        }
+    }
 }
 
-impl RGB {
-    /// Create a new RGB color from individual components.
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Background {
+    type Err = LumosError;
+
+    /// Parse `"dark"`, `"medium"` or `"light"`, ignoring case.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dark" => Ok(Self::Dark),
+            "medium" => Ok(Self::Medium),
+            "light" => Ok(Self::Light),
+            _ => Err(LumosError::InvalidBackground(s.to_string())),
+        }
+    }
+}
+
+/// How the lightness of a color is measured to classify it.
+///
+/// Every classifier gives a lightness between 0.0 (black) and 1.0 (white),
+/// so that the same threshold can be used with all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Classifier {
+    /// WCAG relative luminance, see [`luminance`].
+    ///
+    /// This is physically linear rather than perceptual: mid-gray `#777777`
+    /// has a luminance of about 0.18, so backgrounds have to be quite bright
+    /// to be classified as light.
+    #[default]
+    Luminance,
+    /// CIELAB lightness `L*`, divided by 100, see [`Color::to_lab`].
+    CieLab,
+    /// `OKLab` lightness `L`, see [`Color::to_oklab`].
+    OkLab,
+}
+
+impl Classifier {
+    /// Lowercase name of the classifier (`"luminance"`, `"cielab"` or
+    /// `"oklab"`).
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Luminance => "luminance",
+            Self::CieLab => "cielab",
+            Self::OkLab => "oklab",
+        }
+    }
+
+    /// Lightness of `color`, between 0.0 (black) and 1.0 (white).
+    #[must_use]
+    pub fn lightness(self, color: &Color) -> f64 {
+        match self {
+            Self::Luminance => luminance(color),
+            Self::CieLab => color.to_lab().0 / 100.0,
+            Self::OkLab => color.to_oklab().0,
+        }
+    }
+}
+
+impl fmt::Display for Classifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Classifier {
+    type Err = LumosError;
+
+    /// Parse `"luminance"`, `"cielab"` (or `"lab"`) or `"oklab"`, ignoring
+    /// case.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "luminance" => Ok(Self::Luminance),
+            "cielab" | "lab" => Ok(Self::CieLab),
+            "oklab" => Ok(Self::OkLab),
+            _ => Err(LumosError::InvalidClassifier(s.to_string())),
+        }
+    }
+}
+
+/// Default threshold for determining if a color is dark or light based on
+/// its lightness. Colors with a lightness below this value are considered
+/// dark, whatever the [`Classifier`].
+pub const DEFAULT_THRESHOLD: f64 = 0.5;
+
+/// RGB color with 16-bit red, green, and blue components.
+///
+/// Terminals usually report colors as `rgb:RRRR/GGGG/BBBB`, so components are
+/// kept with 16-bit precision, and only rounded to 8 bits when formatted.
+///
+/// # Examples
+///
+/// ```
+/// # use lumos::Color;
+/// let color: Color = "rgb:1e1e/1e1e/2e2e".parse()?;
+/// assert_eq!(color, Color::from_rgb8(0x1e, 0x1e, 0x2e));
+/// assert_eq!(color.to_string(), "#1e1e2e");
+/// assert_eq!(format!("{color:#}"), "rgb:1e1e/1e1e/2e2e");
+/// # Ok::<(), lumos::LumosError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Color {
+    /// Red component (0-65535)
+    r: u16,
+    /// Green component (0-65535)
+    g: u16,
+    /// Blue component (0-65535)
+    b: u16,
+}
+
+impl Color {
+    /// Create a new color from 16-bit components.
     ///
     /// # Arguments
     ///
-    /// * `r` - Red component (0-255)
-    /// * `g` - Green component (0-255)
-    /// * `b` - Blue component (0-255)
+    /// * `r` - Red component (0-65535)
+    /// * `g` - Green component (0-65535)
+    /// * `b` - Blue component (0-65535)
     ///
     /// # Returns
     ///
-    /// A new RGB struct with the specified components.
-    fn new(r: u8, g: u8, b: u8) -> Self {
+    /// A new Color struct with the specified components.
+    #[must_use]
+    pub const fn new(r: u16, g: u16, b: u16) -> Self {
         Self { r, g, b }
     }
+
+    /// Create a new color from 8-bit components.
+    ///
+    /// Components are scaled so that `0xff` maps to `0xffff`.
+    #[must_use]
+    pub const fn from_rgb8(r: u8, g: u8, b: u8) -> Self {
+        Self::new(scale_u8(r), scale_u8(g), scale_u8(b))
+    }
+
+    /// Create a new color from linear RGB components between 0.0 and 1.0.
+    ///
+    /// This applies the sRGB transfer function, the inverse of
+    /// [`Color::to_linear_rgb`]. Out of range components are clipped.
+    #[must_use]
+    pub fn from_linear_rgb(r: f64, g: f64, b: f64) -> Self {
+        Self::new(delinearize(r), delinearize(g), delinearize(b))
+    }
+
+    /// Create a new color from CIE 1931 XYZ coordinates relative to the D65
+    /// white point, the inverse of [`Color::to_xyz`].
+    ///
+    /// Colors outside of the sRGB gamut are clipped.
+    #[must_use]
+    pub fn from_xyz(x: f64, y: f64, z: f64) -> Self {
+        Self::from_linear_rgb(
+            3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+            -0.969_266_0 * x + 1.876_010_8 * y + 0.041_556_0 * z,
+            0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+        )
+    }
+
+    /// Red component (0-65535).
+    #[must_use]
+    pub const fn red(&self) -> u16 {
+        self.r
+    }
+
+    /// Green component (0-65535).
+    #[must_use]
+    pub const fn green(&self) -> u16 {
+        self.g
+    }
+
+    /// Blue component (0-65535).
+    #[must_use]
+    pub const fn blue(&self) -> u16 {
+        self.b
+    }
+
+    /// Components rounded to 8 bits, as `(r, g, b)`.
+    #[must_use]
+    pub const fn to_rgb8(&self) -> (u8, u8, u8) {
+        (round_u16(self.r), round_u16(self.g), round_u16(self.b))
+    }
+
+    /// Composite this color, with the given `alpha` opacity, over `backdrop`.
+    ///
+    /// This is the "source over" operator of compositing window managers,
+    /// blending the sRGB components: an `alpha` of 1.0 gives this color, and
+    /// 0.0 gives the backdrop.
+    #[must_use]
+    pub fn composite(&self, alpha: f64, backdrop: &Self) -> Self {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let blend = |c: u16, b: u16| {
+            scale_unit((f64::from(c) * alpha + f64::from(b) * (1.0 - alpha)) / 65535.0)
+        };
+        Self::new(
+            blend(self.r, backdrop.r),
+            blend(self.g, backdrop.g),
+            blend(self.b, backdrop.b),
+        )
+    }
+
+    /// Relative luminance of this color, see [`luminance`].
+    #[must_use]
+    pub fn luminance(&self) -> f64 {
+        luminance(self)
+    }
+
+    /// Linear RGB components, as `(r, g, b)` between 0.0 and 1.0.
+    ///
+    /// This undoes the sRGB transfer function (gamma), which most color
+    /// computations need.
+    #[must_use]
+    pub fn to_linear_rgb(&self) -> (f64, f64, f64) {
+        (linearize(self.r), linearize(self.g), linearize(self.b))
+    }
+
+    /// CIE 1931 XYZ coordinates, as `(X, Y, Z)`, relative to the D65 white
+    /// point.
+    ///
+    /// `Y` is the relative luminance, from 0.0 (black) to 1.0 (white).
+    #[must_use]
+    pub fn to_xyz(&self) -> (f64, f64, f64) {
+        let (r, g, b) = self.to_linear_rgb();
+        (
+            0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+            0.212_672_9 * r + 0.715_152_2 * g + 0.072_175_0 * b,
+            0.019_333_9 * r + 0.119_192_0 * g + 0.950_304_1 * b,
+        )
+    }
+
+    /// CIE 1976 L\*a\*b\* coordinates, as `(L*, a*, b*)`, relative to the D65
+    /// white point.
+    ///
+    /// `L*` goes from 0.0 (black) to 100.0 (white), and is perceptually
+    /// uniform: mid-gray `#777777` is about 50.
+    #[must_use]
+    pub fn to_lab(&self) -> (f64, f64, f64) {
+        const DELTA: f64 = 6.0 / 29.0;
+
+        let compand = |t: f64| {
+            if t > DELTA.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+            }
+        };
+
+        let (x, y, z) = self.to_xyz();
+        let (fx, fy, fz) = (compand(x / D65.0), compand(y / D65.1), compand(z / D65.2));
+
+        (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+
+    /// `OKLab` coordinates, as `(L, a, b)`.
+    ///
+    /// `L` goes from 0.0 (black) to 1.0 (white). `OKLab` is more perceptually
+    /// uniform than CIELAB, especially for saturated blues.
+    ///
+    /// See <https://bottosson.github.io/posts/oklab/>.
+    #[must_use]
+    pub fn to_oklab(&self) -> (f64, f64, f64) {
+        let (r, g, b) = self.to_linear_rgb();
+        // Nonlinear responses of the long, medium and short wavelength cones
+        let long = (0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b).cbrt();
+        let medium = (0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b).cbrt();
+        let short = (0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b).cbrt();
+
+        (
+            0.210_454_255_3 * long + 0.793_617_785_0 * medium - 0.004_072_046_8 * short,
+            1.977_998_495_1 * long - 2.428_592_205_0 * medium + 0.450_593_709_9 * short,
+            0.025_904_037_1 * long + 0.782_771_766_2 * medium - 0.808_675_766_0 * short,
+        )
+    }
+
+    /// Create a new color from `OKLab` coordinates, the inverse of
+    /// [`Color::to_oklab`].
+    ///
+    /// Colors outside of the sRGB gamut are clipped.
+    #[must_use]
+    pub fn from_oklab(l: f64, a: f64, b: f64) -> Self {
+        let long = (l + 0.396_337_777_4 * a + 0.215_803_757_3 * b).powi(3);
+        let medium = (l - 0.105_561_345_8 * a - 0.063_854_172_8 * b).powi(3);
+        let short = (l - 0.089_484_177_5 * a - 1.291_485_548_0 * b).powi(3);
+
+        Self::from_linear_rgb(
+            4.076_741_662_1 * long - 3.307_711_591_3 * medium + 0.230_969_929_2 * short,
+            -1.268_438_004_6 * long + 2.609_757_401_1 * medium - 0.341_319_396_5 * short,
+            -0.004_196_086_3 * long - 0.703_418_614_7 * medium + 1.707_614_701_0 * short,
+        )
+    }
 }
 
-/// Parse an RGB color string into RGB struct.
+impl FromStr for Color {
+    type Err = LumosError;
+
+    /// Parse a color string, see [`parse_rgb`] for supported formats.
+    fn from_str(s: &str) -> Result<Self> {
+        parse_rgb(s)
+    }
+}
+
+impl fmt::Display for Color {
+    /// Format as `#rrggbb`, or as `rgb:rrrr/gggg/bbbb` with the alternate
+    /// flag (`{:#}`) to keep the full 16-bit precision.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "rgb:{:04x}/{:04x}/{:04x}", self.r, self.g, self.b)
+        } else {
+            let (r, g, b) = self.to_rgb8();
+            write!(f, "#{r:02x}{g:02x}{b:02x}")
+        }
+    }
+}
+
+/// CIE 1931 XYZ coordinates of the D65 white point, with `Y` = 1.
+pub(crate) const D65: (f64, f64, f64) = (0.950_47, 1.0, 1.088_83);
+
+/// Scale an 8-bit component to 16 bits (`0xab` becomes `0xabab`).
+const fn scale_u8(c: u8) -> u16 {
+    c as u16 * 257
+}
+
+/// Round a 16-bit component to 8 bits.
+#[allow(clippy::cast_possible_truncation)]
+const fn round_u16(c: u16) -> u8 {
+    ((c as u32 * 255 + 32767) / 65535) as u8
+}
+
+/// Parse a color string into a Color struct.
 ///
 /// This function supports multiple color formats commonly returned by terminals:
-/// - `rgb:RRRR/GGGG/BBBB` - X11 RGB format with hex values
-/// - `rgba:RRRR/GGGG/BBBB/AAAA` - X11 RGBA format (alpha ignored)
-/// - `#RRGGBB` - Standard hex color format
-/// - `#RRGGBBAA` - Hex color with alpha (alpha ignored)
-/// - `rgb(R, G, B)` - CSS-style RGB function
+/// - `rgb:RRRR/GGGG/BBBB` - X11 RGB format with 1 to 4 hex digits per component
+/// - `rgba:RRRR/GGGG/BBBB/AAAA` - X11 RGBA format
+/// - `rgbi:`, `CIEXYZ:`, `CIEuvY:`, `CIExyY:`, `CIELab:`, `CIELuv:` and
+///   `TekHVC:` - the other X11 specifications, see `XParseColor(3)`
+/// - `#RGB`, `#RRGGBB`, `#RRRGGGBBB`, `#RRRRGGGGBBBB` - Hex color formats
+/// - `#RGBA`, `#RRGGBBAA` - Hex color with alpha
+/// - `rgb()`, `rgba()`, `hsl()`, `hsla()`, `hwb()`, `oklab()`, `oklch()` and
+///   `color(srgb ...)` - CSS Color Level 4 functions
+/// - Color names, such as `SlateGray` or `navy blue`, from the X11 `rgb.txt`
+///   database and CSS (ignoring case and spaces)
+///
+/// The alpha component of translucent colors is discarded, see [`parse_rgba`]
+/// to keep it.
 ///
 /// # Arguments
 ///
@@ -62,7 +390,7 @@ impl RGB {
 ///
 /// # Returns
 ///
-/// - `Ok(RGB)` with each component in range 0-255
+/// - `Ok(Color)` with each component in range 0-65535
 /// - `Err` if the string cannot be parsed as a valid color
 ///
 /// # Errors
@@ -70,93 +398,99 @@ impl RGB {
 /// This function returns an error in the following cases:
 /// - The string is not in a recognized color format
 /// - A component value is invalid (e.g., non-hex characters, out of range)
-/// - The hex string has an invalid length (not 2 or 4 digits for hex values)
-/// - The RGB values are out of range (0-255)
-pub(crate) fn parse_rgb(s: &str) -> Result<RGB> {
-    let s = s.trim();
-
-    // Handle rgb: or rgba: format
-    if s.starts_with("rgb:") || s.starts_with("rgba:") {
-        let color_part = s
-            .split_once(':')
-            .ok_or_else(|| anyhow!("Invalid rgb: format - missing colon"))?
-            .1;
-        let parts: Vec<&str> = color_part.split('/').collect();
-
-        if parts.len() == 3 || parts.len() == 4 {
-            let r = hex_to_u8(parts[0])
-                .with_context(|| format!("Failed to parse red component: {}", parts[0]))?;
-            let g = hex_to_u8(parts[1])
-                .with_context(|| format!("Failed to parse green component: {}", parts[1]))?;
-            let b = hex_to_u8(parts[2])
-                .with_context(|| format!("Failed to parse blue component: {}", parts[2]))?;
-            return Ok(RGB::new(r, g, b));
-        }
-        return Err(anyhow!(
-            "Invalid rgb: format - expected 3 or 4 components, got {}",
-            parts.len()
-        ));
-    }
-
-    // Handle #hex format
-    if s.starts_with('#') && (s.len() == 7 || s.len() == 9) {
-        let r = u8::from_str_radix(&s[1..3], 16)
-            .with_context(|| format!("Failed to parse red hex component: {}", &s[1..3]))?;
-        let g = u8::from_str_radix(&s[3..5], 16)
-            .with_context(|| format!("Failed to parse green hex component: {}", &s[3..5]))?;
-        let b = u8::from_str_radix(&s[5..7], 16)
-            .with_context(|| format!("Failed to parse blue hex component: {}", &s[5..7]))?;
-        return Ok(RGB::new(r, g, b));
-    }
-
-    // Handle rgb() format
-    if let Some(caps) = RGB_RE.captures(s) {
-        let r = caps[1]
-            .parse::<u8>()
-            .with_context(|| format!("Failed to parse red component: {}", &caps[1]))?;
-        let g = caps[2]
-            .parse::<u8>()
-            .with_context(|| format!("Failed to parse green component: {}", &caps[2]))?;
-        let b = caps[3]
-            .parse::<u8>()
-            .with_context(|| format!("Failed to parse blue component: {}", &caps[3]))?;
-        return Ok(RGB::new(r, g, b));
-    }
-
-    Err(anyhow!("Unrecognized color format: {s}"))
+/// - The hex string has an invalid length
+/// - The component values are out of range (e.g. 0-255 for `rgb()`)
+pub fn parse_rgb(s: &str) -> Result<Color> {
+    parse_rgba(s).map(|(color, _)| color)
 }
 
-/// Convert hex string to u8, handling different hex formats.
+/// Parse a color string, keeping its alpha component.
 ///
-/// This function handles both 2-digit hex values (0-255) and longer hex values
-/// that need to be scaled from 16-bit (0-65535) to 8-bit (0-255) range.
+/// This supports the same formats as [`parse_rgb`], see it for details.
 ///
-/// # Arguments
+/// # Returns
 ///
-/// * `hex` - Hex string without '0x' prefix
+/// - `Ok((Color, alpha))` with `alpha` between 0.0 (transparent) and 1.0
+///   (opaque), which is 1.0 for colors without an alpha component
+/// - `Err` if the string cannot be parsed as a valid color
 ///
-/// # Returns
+/// # Errors
 ///
-/// - `Ok(u8)` - The converted value
-/// - `Err` - If the string is not valid hex
-fn hex_to_u8(hex: &str) -> Result<u8> {
-    let n = u32::from_str_radix(hex, 16).with_context(|| format!("Invalid hex string: {hex}"))?;
+/// See [`parse_rgb`].
+pub fn parse_rgba(s: &str) -> Result<(Color, f64)> {
+    let s = s.trim();
+
+    // Handle X11 specifications (rgb:, rgbi:, CIELab:...)
+    if let Some(result) = xcolor::parse(s) {
+        return result;
+    }
+
+    // Handle #hex format
+    if let Some(hex) = s.strip_prefix('#') {
+        return parse_hex(s, hex);
+    }
 
-    match hex.len() {
-        2 => {
-            // For 2-digit hex values, directly convert to u8
-            #[allow(clippy::cast_possible_truncation)]
-            Ok(n as u8)
+    // Handle CSS functions (rgb(), hsl(), oklch()...)
+    if let Some(result) = css::parse(s) {
+        return result;
+    }
+
+    // Handle X11 and CSS color names
+    if let Some(color) = names::lookup(s) {
+        return Ok((color, 1.0));
+    }
+
+    Err(invalid_color(s, "unrecognized color format"))
+}
+
+/// Parse the digits of a `#` hex color.
+///
+/// Components have 1 (`#rgb`), 2 (`#rrggbb`), 3 (`#rrrgggbbb`) or 4
+/// (`#rrrrggggbbbb`) digits. Short components are scaled like X11 `rgb:`
+/// components, so `#f00` is `#ff0000` like in CSS, while the 3 and 4 digit
+/// ones only exist in legacy X11 colors, and are left-aligned like
+/// `XParseColor` does (`#fff000800` has a blue of `0x8000`). An alpha
+/// component (`#rgba`, `#rrggbbaa`) comes last.
+fn parse_hex(s: &str, hex: &str) -> Result<(Color, f64)> {
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid_color(s, "invalid hex digit"));
+    }
+    let width = match hex.len() {
+        3 | 4 => 1,
+        6 | 8 => 2,
+        9 => 3,
+        12 => 4,
+        len => {
+            return Err(invalid_color(
+                s,
+                format!("expected 3, 4, 6, 8, 9 or 12 hex digits, got {len}"),
+            ));
         }
-        4 => {
-            // For longer hex values, scale from 16-bit to 8-bit using bit-shifting
-            #[allow(clippy::cast_possible_truncation)]
-            Ok((n >> 8) as u8)
+    };
+
+    let component = |i: usize| {
+        let digits = &hex[i * width..(i + 1) * width];
+        if width > 2 {
+            return u16::from_str_radix(digits, 16)
+                .map(|value| value << (16 - 4 * width))
+                .map_err(|e| invalid_color(s, format!("hex component: {e}")));
         }
-        _ => Err(anyhow!(
-            "Invalid hex length: expected 2 or 4 characters, got {}",
-            hex.len()
-        )),
+        xcolor::hex_to_u16(digits).map_err(|e| invalid_color(s, format!("hex component: {e}")))
+    };
+    let color = Color::new(component(0)?, component(1)?, component(2)?);
+    let alpha = if hex.len() == 4 * width {
+        f64::from(component(3)?) / 65535.0
+    } else {
+        1.0
+    };
+    Ok((color, alpha))
+}
+
+/// Build an [`LumosError::InvalidColor`] error for the color string `input`.
+pub(crate) fn invalid_color(input: &str, reason: impl Into<String>) -> LumosError {
+    LumosError::InvalidColor {
+        input: input.to_string(),
+        reason: reason.into(),
     }
 }
 
@@ -169,7 +503,7 @@ fn hex_to_u8(hex: &str) -> Result<u8> {
 ///
 /// # Arguments
 ///
-/// * `rgb` - RGB struct with values 0-255
+/// * `rgb` - Color struct with values 0-65535
 ///
 /// # Returns
 ///
@@ -180,39 +514,161 @@ fn hex_to_u8(hex: &str) -> Result<u8> {
 /// L = 0.2126 × R + 0.7152 × G + 0.0722 × B
 ///
 /// Where R, G, B are the linearized RGB values.
-pub(crate) fn luminance(rgb: &RGB) -> f64 {
-    let r = f64::from(rgb.r) / 255.0;
-    let g = f64::from(rgb.g) / 255.0;
-    let b = f64::from(rgb.b) / 255.0;
-
-    // Convert sRGB component to linear RGB
-    let lin = |c: f64| -> f64 {
-        if c <= 0.04045 {
-            c / 12.92
+#[must_use]
+pub fn luminance(rgb: &Color) -> f64 {
+    let (r, g, b) = rgb.to_linear_rgb();
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Convert a 16-bit sRGB component to linear RGB, between 0.0 and 1.0.
+fn linearize(c: u16) -> f64 {
+    let c = f64::from(c) / 65535.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Apply the sRGB transfer function to a linear component, and scale it to
+/// 16 bits.
+fn delinearize(c: f64) -> u16 {
+    let c = c.clamp(0.0, 1.0);
+    scale_unit(if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    })
+}
+
+/// Scale a component between 0.0 and 1.0 to 16 bits, clipping out of range
+/// values.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn scale_unit(c: f64) -> u16 {
+    (c.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+/// Calculate the WCAG 2.x contrast ratio between two colors.
+///
+/// The ratio goes from 1.0 (no contrast) to 21.0 (black on white), and does
+/// not depend on the order of the colors. WCAG requires at least 4.5 for
+/// normal text (AA), and 7.0 for enhanced contrast (AAA).
+///
+/// # Formula
+///
+/// (L1 + 0.05) / (L2 + 0.05)
+///
+/// Where L1 and L2 are the relative luminances of the lighter and darker colors.
+#[must_use]
+pub fn contrast_ratio(a: &Color, b: &Color) -> f64 {
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// Calculate the APCA lightness contrast (Lc) of `text` over `background`.
+///
+/// APCA (Accessible Perceptual Contrast Algorithm, the candidate contrast
+/// method of WCAG 3) models perceived contrast better than
+/// [`contrast_ratio`], especially for dark themes. Unlike the ratio, it
+/// depends on which color is the text.
+///
+/// # Returns
+///
+/// The Lc value, from about 106 for black text on white to about -108 for
+/// white text on black, positive for dark text on light backgrounds and
+/// negative for light text on dark backgrounds. An absolute value of 75 is
+/// the recommended minimum for body text, 60 for other content text, and 45
+/// for large text.
+///
+/// # Formula
+///
+/// This implements APCA-W3 0.0.98G-4g, see
+/// <https://github.com/Myndex/apca-w3>.
+#[must_use]
+pub fn apca_contrast(text: &Color, background: &Color) -> f64 {
+    // Soft clamp of near-black luminances, accounting for flare
+    const BLACK_THRESHOLD: f64 = 0.022;
+    const BLACK_CLAMP: f64 = 1.414;
+    const SCALE: f64 = 1.14;
+    const OFFSET: f64 = 0.027;
+    const LOW_CLIP: f64 = 0.1;
+    const DELTA_Y_MIN: f64 = 0.0005;
+
+    // APCA uses a simple 2.4 gamma rather than the piecewise sRGB function
+    let screen_luminance = |color: &Color| {
+        let gamma = |c: u16| (f64::from(c) / 65535.0).powf(2.4);
+        let y = 0.212_672_9 * gamma(color.r)
+            + 0.715_152_2 * gamma(color.g)
+            + 0.072_175_0 * gamma(color.b);
+        if y < BLACK_THRESHOLD {
+            y + (BLACK_THRESHOLD - y).powf(BLACK_CLAMP)
         } else {
-            ((c + 0.055) / 1.055).powf(2.4)
+            y
         }
     };
 
-    0.2126 * lin(r) + 0.7152 * lin(g) + 0.0722 * lin(b)
+    let text = screen_luminance(text);
+    let background = screen_luminance(background);
+    if (background - text).abs() < DELTA_Y_MIN {
+        return 0.0;
+    }
+
+    let contrast = if background > text {
+        // Dark text on a light background
+        let sapc = (background.powf(0.56) - text.powf(0.57)) * SCALE;
+        if sapc < LOW_CLIP { 0.0 } else { sapc - OFFSET }
+    } else {
+        // Light text on a dark background
+        let sapc = (background.powf(0.65) - text.powf(0.62)) * SCALE;
+        if sapc > -LOW_CLIP { 0.0 } else { sapc + OFFSET }
+    };
+
+    contrast * 100.0
 }
 
 /// Determine if a color is dark or light based on its luminance.
 ///
+/// This is [`classify_luminance`] using [`DEFAULT_THRESHOLD`].
+///
 /// # Arguments
 ///
-/// * `rgb` - RGB struct with values 0-255
+/// * `rgb` - Color struct with values 0-65535
+#[must_use]
+pub fn classify_color(rgb: &Color) -> Background {
+    classify_luminance(luminance(rgb), DEFAULT_THRESHOLD)
+}
+
+/// Determine if a luminance is dark or light, relative to `threshold`.
+///
+/// # Returns
+///
+/// - `Background::Dark` if luminance < `threshold`
+/// - `Background::Light` if luminance >= `threshold`
+#[must_use]
+pub fn classify_luminance(luminance: f64, threshold: f64) -> Background {
+    classify_lightness(luminance, threshold, 0.0)
+}
+
+/// Determine if a lightness is dark, medium or light, relative to
+/// `threshold`.
+///
+/// Lightness values within `medium_band` of the threshold are classified as
+/// [`Background::Medium`], so a band of 0.0 gives the same result as
+/// [`classify_luminance`].
 ///
 /// # Returns
 ///
-/// - `"dark"` if luminance < `DARK_THRESHOLD`
-/// - `"light"` if luminance >= `DARK_THRESHOLD`
-pub(crate) fn classify_color(rgb: &RGB) -> &'static str {
-    let lum = luminance(rgb);
-    if lum < DARK_THRESHOLD {
-        "dark"
+/// - `Background::Dark` if lightness < `threshold - medium_band`
+/// - `Background::Light` if lightness >= `threshold + medium_band`
+/// - `Background::Medium` otherwise
+#[must_use]
+pub fn classify_lightness(lightness: f64, threshold: f64, medium_band: f64) -> Background {
+    if lightness < threshold - medium_band {
+        Background::Dark
+    } else if lightness >= threshold + medium_band {
+        Background::Light
     } else {
-        "light"
+        Background::Medium
     }
 }
 
@@ -222,122 +678,388 @@ mod tests {
 
     #[test]
     fn test_parse_rgb_hex() -> Result<()> {
-        assert_eq!(parse_rgb("#000000")?, RGB::new(0, 0, 0));
-        assert_eq!(parse_rgb("#ff0000")?, RGB::new(255, 0, 0));
-        assert_eq!(parse_rgb("#00ff00")?, RGB::new(0, 255, 0));
-        assert_eq!(parse_rgb("#0000ff")?, RGB::new(0, 0, 255));
-        assert_eq!(parse_rgb("#ffffff")?, RGB::new(255, 255, 255));
-        assert_eq!(parse_rgb("#ff00ff")?, RGB::new(255, 0, 255));
-        assert_eq!(parse_rgb("#ff0000ff")?, RGB::new(255, 0, 0));
-        assert_eq!(parse_rgb("#AbC123")?, RGB::new(171, 193, 35));
-        assert_eq!(parse_rgb("#001122")?, RGB::new(0, 17, 34));
-        assert_eq!(parse_rgb("  #ff0000  ")?, RGB::new(255, 0, 0));
+        assert_eq!(parse_rgb("#000000")?, Color::from_rgb8(0, 0, 0));
+        assert_eq!(parse_rgb("#ff0000")?, Color::from_rgb8(255, 0, 0));
+        assert_eq!(parse_rgb("#00ff00")?, Color::from_rgb8(0, 255, 0));
+        assert_eq!(parse_rgb("#0000ff")?, Color::from_rgb8(0, 0, 255));
+        assert_eq!(parse_rgb("#ffffff")?, Color::from_rgb8(255, 255, 255));
+        assert_eq!(parse_rgb("#ff00ff")?, Color::from_rgb8(255, 0, 255));
+        assert_eq!(parse_rgb("#ff0000ff")?, Color::from_rgb8(255, 0, 0));
+        assert_eq!(parse_rgb("#AbC123")?, Color::from_rgb8(171, 193, 35));
+        assert_eq!(parse_rgb("#001122")?, Color::from_rgb8(0, 17, 34));
+        assert_eq!(parse_rgb("  #ff0000  ")?, Color::from_rgb8(255, 0, 0));
+
+        assert_eq!(parse_rgb("#f80")?, Color::from_rgb8(255, 136, 0));
+        assert_eq!(parse_rgb("#f80f")?, Color::from_rgb8(255, 136, 0));
+        // Legacy X11 forms are left-aligned
+        assert_eq!(parse_rgb("#fff000800")?, Color::new(0xfff0, 0, 0x8000));
+        assert_eq!(parse_rgb("#ffff00008000")?, Color::new(0xffff, 0, 0x8000));
 
         assert!(parse_rgb("#gg0000").is_err());
-        assert!(parse_rgb("#f00").is_err());
+        assert!(parse_rgb("#").is_err());
+        assert!(parse_rgb("#ff").is_err());
         assert!(parse_rgb("#ff0000ff00").is_err());
+        // Multibyte characters must not be split
+        assert!(parse_rgb("#ééé").is_err());
+        assert!(parse_rgb("#00é0").is_err());
         Ok(())
     }
 
     #[test]
     fn test_parse_rgb_rgb_format() -> Result<()> {
-        assert_eq!(parse_rgb("rgb(0,0,0)")?, RGB::new(0, 0, 0));
-        assert_eq!(parse_rgb("rgb(255,0,0)")?, RGB::new(255, 0, 0));
-        assert_eq!(parse_rgb("rgb(0,255,0)")?, RGB::new(0, 255, 0));
-        assert_eq!(parse_rgb("rgb(0,0,255)")?, RGB::new(0, 0, 255));
-        assert_eq!(parse_rgb("rgb(255,255,255)")?, RGB::new(255, 255, 255));
-        assert_eq!(parse_rgb("rgb(255,0,255)")?, RGB::new(255, 0, 255));
-        assert_eq!(parse_rgb("rgb(171,193,35)")?, RGB::new(171, 193, 35));
-        assert_eq!(parse_rgb("rgb(0,17,34)")?, RGB::new(0, 17, 34));
-        assert_eq!(parse_rgb("  rgb(255,0,0)  ")?, RGB::new(255, 0, 0));
+        assert_eq!(parse_rgb("rgb(0,0,0)")?, Color::from_rgb8(0, 0, 0));
+        assert_eq!(parse_rgb("rgb(255,0,0)")?, Color::from_rgb8(255, 0, 0));
+        assert_eq!(parse_rgb("rgb(0,255,0)")?, Color::from_rgb8(0, 255, 0));
+        assert_eq!(parse_rgb("rgb(0,0,255)")?, Color::from_rgb8(0, 0, 255));
+        assert_eq!(
+            parse_rgb("rgb(255,255,255)")?,
+            Color::from_rgb8(255, 255, 255)
+        );
+        assert_eq!(parse_rgb("rgb(255,0,255)")?, Color::from_rgb8(255, 0, 255));
+        assert_eq!(
+            parse_rgb("rgb(171,193,35)")?,
+            Color::from_rgb8(171, 193, 35)
+        );
+        assert_eq!(parse_rgb("rgb(0,17,34)")?, Color::from_rgb8(0, 17, 34));
+        assert_eq!(parse_rgb("  rgb(255,0,0)  ")?, Color::from_rgb8(255, 0, 0));
 
         assert!(parse_rgb("rgb(0,0,256)").is_err());
         assert!(parse_rgb("rgb(0,0)").is_err());
-        assert!(parse_rgb("rgb(0,0,0,0)").is_err());
+        assert_eq!(parse_rgb("rgb(0,0,0,0)")?, Color::from_rgb8(0, 0, 0));
+        assert_eq!(
+            parse_rgb("rgb(255 0 0 / 50%)")?,
+            Color::from_rgb8(255, 0, 0)
+        );
+        assert_eq!(parse_rgb("hsl(120 100% 50%)")?, Color::from_rgb8(0, 255, 0));
         assert!(parse_rgb("rgb(0,0,0,0,0)").is_err());
         Ok(())
     }
 
     #[test]
     fn test_parse_rgb_rgb_colon_format() -> Result<()> {
-        assert_eq!(parse_rgb("rgb:00/11/22")?, RGB::new(0, 17, 34));
-        assert_eq!(parse_rgb("rgb:ff/00/00")?, RGB::new(255, 0, 0));
-        assert_eq!(parse_rgb("rgb:00/ff/00")?, RGB::new(0, 255, 0));
-        assert_eq!(parse_rgb("rgb:00/00/ff")?, RGB::new(0, 0, 255));
-
-        assert_eq!(parse_rgb("  rgb:00/11/22/33  ")?, RGB::new(0, 17, 34));
-
-        assert_eq!(parse_rgb("rgb:0000/0000/0000")?, RGB::new(0, 0, 0));
-        assert_eq!(parse_rgb("rgb:ffff/0000/0000")?, RGB::new(255, 0, 0));
-        assert_eq!(parse_rgb("rgb:0000/ffff/0000")?, RGB::new(0, 255, 0));
-        assert_eq!(parse_rgb("rgb:0000/0000/ffff")?, RGB::new(0, 0, 255));
-        assert_eq!(parse_rgb("rgb:ffff/ffff/ffff")?, RGB::new(255, 255, 255));
-        assert_eq!(parse_rgb("rgb:ffff/0000/ffff")?, RGB::new(255, 0, 255));
-        assert_eq!(parse_rgb("rgb:abcd/C1AB/230A")?, RGB::new(171, 193, 35));
-        assert_eq!(parse_rgb("rgb:ff00/0000/0000")?, RGB::new(255, 0, 0));
-        assert_eq!(parse_rgb("rgb:1111/2222/3333/4444")?, RGB::new(17, 34, 51));
-        assert_eq!(parse_rgb("rgba:1111/2222/3333/4444")?, RGB::new(17, 34, 51));
+        assert_eq!(parse_rgb("rgb:00/11/22")?, Color::from_rgb8(0, 17, 34));
+        assert_eq!(parse_rgb("rgb:ff/00/00")?, Color::from_rgb8(255, 0, 0));
+        assert_eq!(parse_rgb("rgb:00/ff/00")?, Color::from_rgb8(0, 255, 0));
+        assert_eq!(parse_rgb("rgb:00/00/ff")?, Color::from_rgb8(0, 0, 255));
+
+        assert_eq!(
+            parse_rgb("  rgb:00/11/22/33  ")?,
+            Color::from_rgb8(0, 17, 34)
+        );
+
+        assert_eq!(parse_rgb("rgb:0000/0000/0000")?, Color::from_rgb8(0, 0, 0));
+        assert_eq!(
+            parse_rgb("rgb:ffff/0000/0000")?,
+            Color::from_rgb8(255, 0, 0)
+        );
+        assert_eq!(
+            parse_rgb("rgb:0000/ffff/0000")?,
+            Color::from_rgb8(0, 255, 0)
+        );
+        assert_eq!(
+            parse_rgb("rgb:0000/0000/ffff")?,
+            Color::from_rgb8(0, 0, 255)
+        );
+        assert_eq!(
+            parse_rgb("rgb:ffff/ffff/ffff")?,
+            Color::from_rgb8(255, 255, 255)
+        );
+        assert_eq!(
+            parse_rgb("rgb:ffff/0000/ffff")?,
+            Color::from_rgb8(255, 0, 255)
+        );
+        assert_eq!(
+            parse_rgb("rgb:abcd/C1AB/230A")?,
+            Color::new(0xabcd, 0xc1ab, 0x230a)
+        );
+        assert_eq!(parse_rgb("rgb:ff00/0000/0000")?, Color::new(0xff00, 0, 0));
+        assert_eq!(
+            parse_rgb("rgb:7fff/8000/8080")?,
+            Color::new(0x7fff, 0x8000, 0x8080)
+        );
+        assert_eq!(
+            parse_rgb("rgb:1111/2222/3333/4444")?,
+            Color::from_rgb8(17, 34, 51)
+        );
+        assert_eq!(
+            parse_rgb("rgba:1111/2222/3333/4444")?,
+            Color::from_rgb8(17, 34, 51)
+        );
 
         assert!(parse_rgb("rgb:gggg/gggg/gggg").is_err());
-        assert!(parse_rgb("rgb:000/000/000").is_err());
+        assert_eq!(parse_rgb("rgb:f/0/0")?, Color::from_rgb8(255, 0, 0));
+        assert_eq!(parse_rgb("rgb:fff/000/000")?, Color::from_rgb8(255, 0, 0));
+        assert_eq!(
+            parse_rgb("CIELab:100/0/0")?,
+            Color::from_rgb8(255, 255, 255)
+        );
         assert!(parse_rgb("rgb:00000/00000/00000").is_err());
         assert!(parse_rgb("rgb:0000/0000/0000/0000/0000").is_err());
         Ok(())
     }
 
-    #[test]
-    fn test_hex_to_u8() -> Result<()> {
-        assert_eq!(hex_to_u8("00")?, 0);
-        assert_eq!(hex_to_u8("ff")?, 255);
-        assert_eq!(hex_to_u8("ffff")?, 255);
-        assert_eq!(hex_to_u8("0000")?, 0);
-        assert_eq!(hex_to_u8("8000")?, 128);
-        assert_eq!(hex_to_u8("7fff")?, 127);
-        assert_eq!(hex_to_u8("0080")?, 0);
-        assert_eq!(hex_to_u8("1234")?, 18);
-        assert_eq!(hex_to_u8("abcd")?, 171);
-
-        assert!(hex_to_u8("00000").is_err());
-        assert!(hex_to_u8("123").is_err());
-        assert!(hex_to_u8("xyz").is_err());
-        assert!(hex_to_u8("").is_err());
-        Ok(())
-    }
-
     #[test]
     fn test_luminance() {
-        assert!((luminance(&RGB::new(0, 0, 0)) - 0.0).abs() < 0.001);
-        assert!((luminance(&RGB::new(255, 255, 255)) - 1.0).abs() < 0.001);
+        assert!((luminance(&Color::from_rgb8(0, 0, 0)) - 0.0).abs() < 0.001);
+        assert!((luminance(&Color::from_rgb8(255, 255, 255)) - 1.0).abs() < 0.001);
         // Test a mid-gray
-        let mid_gray_lum = luminance(&RGB::new(128, 128, 128));
+        let mid_gray_lum = luminance(&Color::from_rgb8(128, 128, 128));
         assert!(mid_gray_lum > 0.0 && mid_gray_lum < 1.0);
         // Test colors with different luminance contributions
-        assert!((luminance(&RGB::new(255, 0, 0)) - 0.2126).abs() < 0.001); // Red should have low luminance
-        assert!((luminance(&RGB::new(0, 255, 0)) - 0.7152).abs() < 0.001); // Green should have high luminance
-        assert!((luminance(&RGB::new(0, 0, 255)) - 0.0722).abs() < 0.001); // Blue should have very low luminance
+        assert!((luminance(&Color::from_rgb8(255, 0, 0)) - 0.2126).abs() < 0.001); // Red should have low luminance
+        assert!((luminance(&Color::from_rgb8(0, 255, 0)) - 0.7152).abs() < 0.001); // Green should have high luminance
+        assert!((luminance(&Color::from_rgb8(0, 0, 255)) - 0.0722).abs() < 0.001); // Blue should have very low luminance
         // Test edge cases with non-linear conversion
-        assert!((luminance(&RGB::new(0, 0, 0)) - 0.0).abs() < 0.001);
-        assert!((luminance(&RGB::new(255, 255, 255)) - 1.0).abs() < 0.001);
+        assert!((luminance(&Color::from_rgb8(0, 0, 0)) - 0.0).abs() < 0.001);
+        assert!((luminance(&Color::from_rgb8(255, 255, 255)) - 1.0).abs() < 0.001);
         // Test a subtle color difference that should be distinguishable
-        let very_dark = luminance(&RGB::new(1, 1, 1));
-        let slightly_lighter = luminance(&RGB::new(2, 2, 2));
+        let very_dark = luminance(&Color::from_rgb8(1, 1, 1));
+        let slightly_lighter = luminance(&Color::from_rgb8(2, 2, 2));
         assert!(slightly_lighter > very_dark);
     }
 
+    #[test]
+    fn test_luminance_precision() -> Result<()> {
+        // Components differing below 8 bits keep distinct luminances
+        let low = parse_rgb("rgb:7f00/7f00/7f00")?;
+        let high = parse_rgb("rgb:7fff/7fff/7fff")?;
+        assert_eq!(low.to_rgb8(), high.to_rgb8());
+        assert!(high.luminance() > low.luminance());
+
+        // A threshold between them tells them apart, whereas truncating to
+        // 8 bits would classify both as dark
+        let threshold = f64::midpoint(low.luminance(), high.luminance());
+        assert_eq!(
+            classify_luminance(low.luminance(), threshold),
+            Background::Dark
+        );
+        assert_eq!(
+            classify_luminance(high.luminance(), threshold),
+            Background::Light
+        );
+        assert_eq!(
+            classify_luminance(Color::from_rgb8(0x7f, 0x7f, 0x7f).luminance(), threshold),
+            Background::Dark
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let black = Color::from_rgb8(0, 0, 0);
+        let white = Color::from_rgb8(255, 255, 255);
+        assert!((contrast_ratio(&black, &white) - 21.0).abs() < 0.001);
+        assert!((contrast_ratio(&white, &black) - 21.0).abs() < 0.001);
+        assert!((contrast_ratio(&white, &white) - 1.0).abs() < 0.001);
+        // #777777 on white is a well known borderline AA case
+        let gray = Color::from_rgb8(0x77, 0x77, 0x77);
+        assert!((contrast_ratio(&gray, &white) - 4.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_apca_contrast() {
+        let black = Color::from_rgb8(0, 0, 0);
+        let white = Color::from_rgb8(255, 255, 255);
+        let gray = Color::from_rgb8(0x88, 0x88, 0x88);
+        // Reference values from https://github.com/Myndex/apca-w3
+        assert!((apca_contrast(&black, &white) - 106.04).abs() < 0.01);
+        assert!((apca_contrast(&white, &black) + 107.88).abs() < 0.01);
+        assert!((apca_contrast(&gray, &white) - 63.06).abs() < 0.01);
+        assert!((apca_contrast(&white, &gray) + 68.54).abs() < 0.01);
+        assert!(apca_contrast(&gray, &gray).abs() < f64::EPSILON);
+    }
+
     #[test]
     fn test_classify_color() {
-        assert_eq!(classify_color(&RGB::new(0, 0, 0)), "dark");
-        assert_eq!(classify_color(&RGB::new(255, 255, 255)), "light");
-        assert_eq!(classify_color(&RGB::new(128, 128, 128)), "dark"); // Mid-gray is below threshold
-        assert_eq!(classify_color(&RGB::new(200, 200, 200)), "light");
-        assert_eq!(classify_color(&RGB::new(50, 50, 50)), "dark");
+        assert_eq!(classify_color(&Color::from_rgb8(0, 0, 0)), Background::Dark);
+        assert_eq!(
+            classify_color(&Color::from_rgb8(255, 255, 255)),
+            Background::Light
+        );
+        assert_eq!(
+            classify_color(&Color::from_rgb8(128, 128, 128)),
+            Background::Dark
+        ); // Mid-gray is below threshold
+        assert_eq!(
+            classify_color(&Color::from_rgb8(200, 200, 200)),
+            Background::Light
+        );
+        assert_eq!(
+            classify_color(&Color::from_rgb8(50, 50, 50)),
+            Background::Dark
+        );
+    }
+
+    #[test]
+    fn test_to_lab() {
+        let close = |a: (f64, f64, f64), b: (f64, f64, f64)| {
+            (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01 && (a.2 - b.2).abs() < 0.01
+        };
+        assert!(close(Color::from_rgb8(0, 0, 0).to_lab(), (0.0, 0.0, 0.0)));
+        assert!(close(
+            Color::from_rgb8(255, 255, 255).to_lab(),
+            (100.0, 0.0, 0.0)
+        ));
+        // Reference values from https://www.easyrgb.com/
+        let lab = Color::from_rgb8(255, 0, 0).to_lab();
+        assert!(close(lab, (53.24, 80.09, 67.20)), "{lab:?}");
+        let lab = Color::from_rgb8(0x77, 0x77, 0x77).to_lab();
+        assert!((lab.0 - 50.03).abs() < 0.01, "{lab:?}");
+    }
+
+    #[test]
+    fn test_to_oklab() {
+        let close = |a: (f64, f64, f64), b: (f64, f64, f64)| {
+            (a.0 - b.0).abs() < 0.001 && (a.1 - b.1).abs() < 0.001 && (a.2 - b.2).abs() < 0.001
+        };
+        assert!(close(Color::from_rgb8(0, 0, 0).to_oklab(), (0.0, 0.0, 0.0)));
+        assert!(close(
+            Color::from_rgb8(255, 255, 255).to_oklab(),
+            (1.0, 0.0, 0.0)
+        ));
+        // Reference values from https://bottosson.github.io/posts/oklab/
+        let oklab = Color::from_rgb8(255, 0, 0).to_oklab();
+        assert!(close(oklab, (0.628, 0.225, 0.126)), "{oklab:?}");
+        let oklab = Color::from_rgb8(0, 0, 255).to_oklab();
+        assert!(close(oklab, (0.452, -0.032, -0.312)), "{oklab:?}");
+    }
+
+    #[test]
+    fn test_classifier() -> Result<()> {
+        assert_eq!("OkLab".parse::<Classifier>()?, Classifier::OkLab);
+        assert_eq!("lab".parse::<Classifier>()?, Classifier::CieLab);
+        assert!("hsl".parse::<Classifier>().is_err());
+        assert_eq!(Classifier::default().to_string(), "luminance");
+
+        // Gruvbox gray, between the physical and perceptual middles
+        let gray = Color::from_rgb8(0x92, 0x83, 0x74);
+        assert!(Classifier::Luminance.lightness(&gray) < DEFAULT_THRESHOLD);
+        assert!(Classifier::CieLab.lightness(&gray) > DEFAULT_THRESHOLD);
+        assert!(Classifier::OkLab.lightness(&gray) > DEFAULT_THRESHOLD);
+
+        for classifier in [Classifier::Luminance, Classifier::CieLab, Classifier::OkLab] {
+            let black = classifier.lightness(&Color::from_rgb8(0, 0, 0));
+            let white = classifier.lightness(&Color::from_rgb8(255, 255, 255));
+            assert!(black.abs() < 0.001, "{classifier}");
+            assert!((white - 1.0).abs() < 0.001, "{classifier}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_lightness() {
+        assert_eq!(classify_lightness(0.39, 0.5, 0.1), Background::Dark);
+        assert_eq!(classify_lightness(0.4, 0.5, 0.1), Background::Medium);
+        assert_eq!(classify_lightness(0.59, 0.5, 0.1), Background::Medium);
+        assert_eq!(classify_lightness(0.6, 0.5, 0.1), Background::Light);
+        assert_eq!(classify_lightness(0.49, 0.5, 0.0), Background::Dark);
+        assert_eq!(classify_lightness(0.5, 0.5, 0.0), Background::Light);
     }
 
     #[test]
-    fn test_rgb_struct() {
-        let rgb = RGB::new(100, 150, 200);
-        assert_eq!(rgb.r, 100);
-        assert_eq!(rgb.g, 150);
-        assert_eq!(rgb.b, 200);
+    fn test_classify_luminance() {
+        assert_eq!(classify_luminance(0.2, 0.5), Background::Dark);
+        assert_eq!(classify_luminance(0.5, 0.5), Background::Light);
+        assert_eq!(classify_luminance(0.2, 0.1), Background::Light);
+        assert_eq!(classify_luminance(0.9, 1.0), Background::Dark);
+    }
+
+    #[test]
+    fn test_background() -> Result<()> {
+        assert_eq!(Background::Dark.to_string(), "dark");
+        assert_eq!(Background::Light.as_str(), "light");
+        assert_eq!("dark".parse::<Background>()?, Background::Dark);
+        assert_eq!(" Light ".parse::<Background>()?, Background::Light);
+        assert_eq!("medium".parse::<Background>()?, Background::Medium);
+        assert_eq!(Background::Medium.to_string(), "medium");
+        assert!("unknown".parse::<Background>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_struct() {
+        let color = Color::new(100, 150, 200);
+        assert_eq!(color.red(), 100);
+        assert_eq!(color.green(), 150);
+        assert_eq!(color.blue(), 200);
+
+        let color = Color::from_rgb8(0x12, 0x80, 0xff);
+        assert_eq!(color, Color::new(0x1212, 0x8080, 0xffff));
+        assert_eq!(color.to_rgb8(), (0x12, 0x80, 0xff));
+    }
+
+    #[test]
+    fn test_color_to_rgb8_rounding() {
+        assert_eq!(Color::new(0x7fff, 0x8000, 0x0080).to_rgb8(), (127, 128, 0));
+        assert_eq!(Color::new(0x0081, 0xff00, 0xff80).to_rgb8(), (1, 254, 255));
+        assert_eq!(Color::new(0, 0xffff, 0x8080).to_rgb8(), (0, 255, 128));
+    }
+
+    #[test]
+    fn test_composite() {
+        let white = Color::from_rgb8(255, 255, 255);
+        let black = Color::from_rgb8(0, 0, 0);
+        let color = Color::new(0x1234, 0x5678, 0x9abc);
+
+        assert_eq!(color.composite(1.0, &white), color);
+        assert_eq!(color.composite(0.0, &white), white);
+        assert_eq!(
+            white.composite(0.5, &black),
+            Color::new(0x8000, 0x8000, 0x8000)
+        );
+        assert_eq!(
+            black.composite(0.25, &white),
+            Color::new(0xbfff, 0xbfff, 0xbfff)
+        );
+        assert_eq!(color.composite(2.0, &black), color);
+    }
+
+    #[test]
+    fn test_parse_rgba() -> Result<()> {
+        let (color, alpha) = parse_rgba("#ff000080")?;
+        assert_eq!(color, Color::from_rgb8(255, 0, 0));
+        assert!((alpha - 128.0 / 255.0).abs() < 1e-9);
+
+        let cases = [
+            ("#f008", f64::from(0x8888) / 65535.0),
+            ("rgba:0000/0000/0000/8000", 32768.0 / 65535.0),
+            ("rgb(0 0 0 / 25%)", 0.25),
+            ("#ff0000", 1.0),
+            ("navy", 1.0),
+        ];
+        for (s, expected) in cases {
+            let (_, alpha) = parse_rgba(s)?;
+            assert!((alpha - expected).abs() < 1e-9, "{s}: {alpha}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_display() {
+        let color = Color::new(0xabcd, 0xc1ab, 0x230a);
+        assert_eq!(color.to_string(), "#abc123");
+        assert_eq!(format!("{color:#}"), "rgb:abcd/c1ab/230a");
+        assert_eq!(Color::from_rgb8(255, 0, 0).to_string(), "#ff0000");
+    }
+
+    #[test]
+    fn test_color_from_str() -> Result<()> {
+        assert_eq!("#ff8000".parse::<Color>()?, Color::from_rgb8(255, 128, 0));
+        assert_eq!(
+            "rgb:1234/5678/9abc".parse::<Color>()?,
+            Color::new(0x1234, 0x5678, 0x9abc)
+        );
+        assert_eq!(
+            "  Navy Blue ".parse::<Color>()?,
+            Color::from_rgb8(0, 0, 128)
+        );
+        assert!("nope".parse::<Color>().is_err());
+
+        // Round trips
+        let color = Color::new(0x1234, 0x5678, 0x9abc);
+        assert_eq!(format!("{color:#}").parse::<Color>()?, color);
+        let color = Color::from_rgb8(1, 2, 3);
+        assert_eq!(color.to_string().parse::<Color>()?, color);
+        Ok(())
     }
 }
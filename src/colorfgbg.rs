@@ -0,0 +1,105 @@
+//! `$COLORFGBG` fallback, for terminals that cannot be queried.
+//!
+//! rxvt and Konsole export the palette indices of their default colors as
+//! `<fg>;<bg>`, or `<fg>;<xpm>;<bg>` when rxvt is built with background
+//! pixmaps, where any index can be `default`. The indices are mapped to
+//! colors with the standard xterm palette, the actual one being unknown.
+
+use crate::color::Color;
+
+/// The 16 ANSI colors of the xterm palette.
+const ANSI: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xcd, 0x00, 0x00],
+    [0x00, 0xcd, 0x00],
+    [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee],
+    [0xcd, 0x00, 0xcd],
+    [0x00, 0xcd, 0xcd],
+    [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f],
+    [0xff, 0x00, 0x00],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+/// Color of a palette entry: the 16 ANSI colors, then the 6x6x6 color cube
+/// and the grayscale ramp of 256-color terminals.
+pub(crate) fn palette_color(index: u8) -> Color {
+    let rgb8 = |[r, g, b]: [u8; 3]| {
+        Color::new(
+            u16::from(r) * 0x101,
+            u16::from(g) * 0x101,
+            u16::from(b) * 0x101,
+        )
+    };
+    let level = |n: u8| if n == 0 { 0 } else { 55 + 40 * n };
+
+    match index {
+        0..=15 => rgb8(ANSI[usize::from(index)]),
+        16..=231 => {
+            let n = index - 16;
+            rgb8([level(n / 36), level(n / 6 % 6), level(n % 6)])
+        }
+        232..=255 => {
+            let gray = 8 + 10 * (index - 232);
+            rgb8([gray; 3])
+        }
+    }
+}
+
+/// Parse a `$COLORFGBG` value into its foreground and background colors.
+///
+/// Returns `None` unless the background is a palette index, the foreground
+/// being `None` if it is `default` (or invalid).
+pub(crate) fn parse(value: &str) -> Option<(Option<Color>, Color)> {
+    let fields: Vec<&str> = value.split(';').collect();
+    let ([foreground, background] | [foreground, _, background]) = fields[..] else {
+        return None;
+    };
+    let color = |index: &str| index.trim().parse().ok().map(palette_color);
+
+    Some((color(foreground), color(background)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_color() {
+        assert_eq!(palette_color(0), Color::new(0, 0, 0));
+        assert_eq!(palette_color(4), Color::new(0, 0, 0xeeee));
+        assert_eq!(palette_color(15), Color::new(0xffff, 0xffff, 0xffff));
+        assert_eq!(palette_color(16), Color::new(0, 0, 0));
+        assert_eq!(palette_color(67), Color::new(0x5f5f, 0x8787, 0xafaf));
+        assert_eq!(palette_color(231), Color::new(0xffff, 0xffff, 0xffff));
+        assert_eq!(palette_color(232), Color::new(0x0808, 0x0808, 0x0808));
+        assert_eq!(palette_color(255), Color::new(0xeeee, 0xeeee, 0xeeee));
+    }
+
+    #[test]
+    fn test_parse() {
+        let (black, white) = (palette_color(0), palette_color(15));
+
+        assert_eq!(parse("15;0"), Some((Some(white.clone()), black.clone())));
+        assert_eq!(parse("0;15"), Some((Some(black.clone()), white.clone())));
+        assert_eq!(parse("0;default;15"), Some((Some(black), white.clone())));
+        assert_eq!(parse("default;15"), Some((None, white)));
+        assert_eq!(
+            parse("12;236"),
+            Some((Some(palette_color(12)), palette_color(236)))
+        );
+
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("15"), None);
+        assert_eq!(parse("15;default"), None);
+        assert_eq!(parse("15;256"), None);
+        assert_eq!(parse("15;-1"), None);
+        assert_eq!(parse("0;1;2;3"), None);
+    }
+}
@@ -0,0 +1,331 @@
+//! CSS color functions, as defined by CSS Color Module Level 4.
+//!
+//! Supported functions are `rgb()`, `rgba()`, `hsl()`, `hsla()`, `hwb()`,
+//! `oklab()`, `oklch()` and `color()` with the `srgb` and `srgb-linear`
+//! color spaces. Both the legacy comma-separated syntax and the modern
+//! space-separated one (with an optional `/ alpha`) are accepted, as well as
+//! percentages and the `none` keyword.
+//!
+//! Unlike browsers, which clamp them, out of range components are rejected:
+//! a theme file with `rgb(300, 0, 0)` is more likely broken than very red.
+
+use std::ops::RangeInclusive;
+
+use crate::color::{Color, invalid_color, scale_unit};
+use crate::error::Result;
+use crate::xcolor::check;
+
+/// Range of components without limits.
+const UNBOUNDED: RangeInclusive<f64> = f64::NEG_INFINITY..=f64::INFINITY;
+
+/// The components of a color function, and its optional alpha.
+struct Arguments<'a> {
+    components: Vec<&'a str>,
+    alpha: Option<&'a str>,
+}
+
+/// Parse a CSS color function, and its alpha (1.0 when opaque).
+///
+/// Returns `None` when `s` is not a known function, so that other formats
+/// can be tried.
+pub(crate) fn parse(s: &str) -> Option<Result<(Color, f64)>> {
+    let (function, rest) = s.split_once('(')?;
+    let function = function.trim().to_ascii_lowercase();
+    if !matches!(
+        function.as_str(),
+        "rgb" | "rgba" | "hsl" | "hsla" | "hwb" | "oklab" | "oklch" | "color"
+    ) {
+        return None;
+    }
+
+    let Some(arguments) = rest.trim_end().strip_suffix(')') else {
+        return Some(Err(invalid_color(s, "missing closing parenthesis")));
+    };
+
+    // The color space of `color()` comes before the components
+    let count = if function == "color" { 4 } else { 3 };
+    Some(arguments_of(s, arguments, count).and_then(|arguments| {
+        let alpha = match arguments.alpha {
+            Some(alpha) => component(s, "alpha", alpha, 1.0, 0.0..=1.0)?,
+            None => 1.0,
+        };
+        let color = match function.as_str() {
+            "rgb" | "rgba" => rgb(s, &arguments.components),
+            "hsl" | "hsla" => hsl(s, &arguments.components),
+            "hwb" => hwb(s, &arguments.components),
+            "oklab" => oklab(s, &arguments.components),
+            "oklch" => oklch(s, &arguments.components),
+            _ => color(s, &arguments.components),
+        }?;
+        Ok((color, alpha))
+    }))
+}
+
+/// Split the arguments of a color function into `count` components and
+/// alpha.
+fn arguments_of<'a>(s: &str, arguments: &'a str, count: usize) -> Result<Arguments<'a>> {
+    let (components, alpha) = if arguments.contains(',') {
+        // Legacy syntax: `rgb(255, 128, 0, 0.5)`
+        let mut components: Vec<&str> = arguments.split(',').map(str::trim).collect();
+        let alpha = (components.len() == count + 1)
+            .then(|| components.pop())
+            .flatten();
+        (components, alpha)
+    } else {
+        // Modern syntax: `rgb(255 128 0 / 0.5)`
+        let (components, alpha) = match arguments.split_once('/') {
+            Some((components, alpha)) => (components, Some(alpha.trim())),
+            None => (arguments, None),
+        };
+        (components.split_whitespace().collect(), alpha)
+    };
+
+    if components.len() != count || components.iter().any(|c| c.is_empty()) {
+        return Err(invalid_color(
+            s,
+            format!("expected {count} components, got {}", components.len()),
+        ));
+    }
+    Ok(Arguments { components, alpha })
+}
+
+/// Parse a number, or `none` (zero).
+fn number(s: &str, name: &str, token: &str) -> Result<f64> {
+    if token.eq_ignore_ascii_case("none") {
+        return Ok(0.0);
+    }
+    token
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| invalid_color(s, format!("{name} component: invalid number {token:?}")))
+}
+
+/// Parse a number or a percentage of `full`, and check that it is within
+/// `range`.
+fn component(
+    s: &str,
+    name: &str,
+    token: &str,
+    full: f64,
+    range: RangeInclusive<f64>,
+) -> Result<f64> {
+    let value = match token.strip_suffix('%') {
+        Some(percentage) => number(s, name, percentage)? / 100.0 * full,
+        None => number(s, name, token)?,
+    };
+    check(s, name, value, range)
+}
+
+/// Parse a hue, in degrees unless another angle unit is given.
+fn hue(s: &str, token: &str) -> Result<f64> {
+    let token = token.to_ascii_lowercase();
+    let degrees = if let Some(value) = token.strip_suffix("deg") {
+        number(s, "hue", value)?
+    } else if let Some(value) = token.strip_suffix("grad") {
+        number(s, "hue", value)? * 0.9
+    } else if let Some(value) = token.strip_suffix("rad") {
+        number(s, "hue", value)?.to_degrees()
+    } else if let Some(value) = token.strip_suffix("turn") {
+        number(s, "hue", value)? * 360.0
+    } else {
+        number(s, "hue", &token)?
+    };
+    Ok(degrees.rem_euclid(360.0))
+}
+
+/// `rgb(R G B)`, with components from 0 to 255, or percentages.
+fn rgb(s: &str, components: &[&str]) -> Result<Color> {
+    let r = component(s, "red", components[0], 255.0, 0.0..=255.0)?;
+    let g = component(s, "green", components[1], 255.0, 0.0..=255.0)?;
+    let b = component(s, "blue", components[2], 255.0, 0.0..=255.0)?;
+    Ok(Color::new(
+        scale_unit(r / 255.0),
+        scale_unit(g / 255.0),
+        scale_unit(b / 255.0),
+    ))
+}
+
+/// sRGB components, between 0.0 and 1.0, of a hue with the given saturation
+/// and lightness.
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (f64, f64, f64) {
+    let chroma = saturation * lightness.min(1.0 - lightness);
+    let channel = |n: f64| {
+        let k = (n + hue / 30.0) % 12.0;
+        lightness - chroma * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (channel(0.0), channel(8.0), channel(4.0))
+}
+
+/// `hsl(H S L)`, with saturation and lightness from 0 to 100.
+fn hsl(s: &str, components: &[&str]) -> Result<Color> {
+    let angle = hue(s, components[0])?;
+    let saturation = component(s, "saturation", components[1], 100.0, 0.0..=100.0)?;
+    let lightness = component(s, "lightness", components[2], 100.0, 0.0..=100.0)?;
+
+    let (red, green, blue) = hsl_to_rgb(angle, saturation / 100.0, lightness / 100.0);
+    Ok(Color::new(
+        scale_unit(red),
+        scale_unit(green),
+        scale_unit(blue),
+    ))
+}
+
+/// `hwb(H W B)`, with whiteness and blackness from 0 to 100.
+fn hwb(s: &str, components: &[&str]) -> Result<Color> {
+    let angle = hue(s, components[0])?;
+    let whiteness = component(s, "whiteness", components[1], 100.0, 0.0..=100.0)? / 100.0;
+    let blackness = component(s, "blackness", components[2], 100.0, 0.0..=100.0)? / 100.0;
+
+    if whiteness + blackness >= 1.0 {
+        let gray = scale_unit(whiteness / (whiteness + blackness));
+        return Ok(Color::new(gray, gray, gray));
+    }
+    let (red, green, blue) = hsl_to_rgb(angle, 1.0, 0.5);
+    let scale = |c: f64| scale_unit(c * (1.0 - whiteness - blackness) + whiteness);
+    Ok(Color::new(scale(red), scale(green), scale(blue)))
+}
+
+/// `oklab(L a b)`, with `L` from 0 to 1, and 100% of `a` and `b` being 0.4.
+fn oklab(s: &str, components: &[&str]) -> Result<Color> {
+    let l = component(s, "L", components[0], 1.0, 0.0..=1.0)?;
+    let a = component(s, "a", components[1], 0.4, UNBOUNDED)?;
+    let b = component(s, "b", components[2], 0.4, UNBOUNDED)?;
+    Ok(Color::from_oklab(l, a, b))
+}
+
+/// `oklch(L C H)`, the polar form of `oklab()`.
+fn oklch(s: &str, components: &[&str]) -> Result<Color> {
+    let l = component(s, "L", components[0], 1.0, 0.0..=1.0)?;
+    let c = component(s, "C", components[1], 0.4, 0.0..=f64::INFINITY)?;
+    let h = hue(s, components[2])?.to_radians();
+    Ok(Color::from_oklab(l, c * h.cos(), c * h.sin()))
+}
+
+/// `color(srgb R G B)` and `color(srgb-linear R G B)`, with components from
+/// 0 to 1.
+fn color(s: &str, components: &[&str]) -> Result<Color> {
+    let [space, r, g, b] = components else {
+        return Err(invalid_color(s, "missing color space"));
+    };
+    let r = component(s, "red", r, 1.0, 0.0..=1.0)?;
+    let g = component(s, "green", g, 1.0, 0.0..=1.0)?;
+    let b = component(s, "blue", b, 1.0, 0.0..=1.0)?;
+
+    match space.to_ascii_lowercase().as_str() {
+        "srgb" => Ok(Color::new(scale_unit(r), scale_unit(g), scale_unit(b))),
+        "srgb-linear" => Ok(Color::from_linear_rgb(r, g, b)),
+        space => Err(invalid_color(
+            s,
+            format!("unsupported color space {space:?}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rgb() -> Result<()> {
+        let cases = [
+            ("rgb(255,128,0)", Color::from_rgb8(255, 128, 0)),
+            ("rgb(255, 128, 0, 0.5)", Color::from_rgb8(255, 128, 0)),
+            ("rgba(255, 128, 0, 50%)", Color::from_rgb8(255, 128, 0)),
+            ("rgb(255 128 0)", Color::from_rgb8(255, 128, 0)),
+            ("rgb(255 128 0 / 0.5)", Color::from_rgb8(255, 128, 0)),
+            ("RGB( 255  128  0 )", Color::from_rgb8(255, 128, 0)),
+            ("rgb(100% 50% 0%)", Color::new(0xffff, 0x8000, 0)),
+            ("rgb(127.5 0 none)", Color::new(0x8000, 0, 0)),
+        ];
+        for (s, expected) in cases {
+            let (color, _) = parse(s).expect(s)?;
+            assert_eq!(color, expected, "{s}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_alpha() -> Result<()> {
+        let cases = [
+            ("rgb(0 0 0)", 1.0),
+            ("rgb(0, 0, 0, 0.25)", 0.25),
+            ("rgba(0 0 0 / 40%)", 0.4),
+            ("hsl(0 0% 0% / none)", 0.0),
+            ("color(srgb 0 0 0 / 0.5)", 0.5),
+        ];
+        for (s, expected) in cases {
+            let (_, alpha) = parse(s).expect(s)?;
+            assert!((alpha - expected).abs() < 1e-9, "{s}: {alpha}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_functions() -> Result<()> {
+        // Compared as 8-bit colors, to allow for rounding errors
+        let cases = [
+            ("hsl(0, 100%, 50%)", (255, 0, 0)),
+            ("hsl(120 100% 25%)", (0, 128, 0)),
+            ("hsla(240deg 100% 50% / 0.5)", (0, 0, 255)),
+            ("hsl(0.5turn 100 50)", (0, 255, 255)),
+            ("hsl(-60 100% 50%)", (255, 0, 255)),
+            ("hsl(30 0% 50%)", (128, 128, 128)),
+            ("hsl(3.14159rad 100% 50%)", (0, 255, 255)),
+            ("hsl(400grad 100% 50%)", (255, 0, 0)),
+            ("hwb(0 0% 0%)", (255, 0, 0)),
+            ("hwb(120 20% 30%)", (51, 179, 51)),
+            ("hwb(0 60% 60%)", (128, 128, 128)),
+            ("oklab(0 0 0)", (0, 0, 0)),
+            ("oklab(1 0 0)", (255, 255, 255)),
+            ("oklab(62.8% 0.225 0.126)", (255, 0, 0)),
+            ("oklab(0.452 -0.0325 -77.87%)", (0, 0, 255)),
+            ("oklch(0.628 0.2577 29.23)", (255, 0, 0)),
+            ("oklch(0.866 0.295 142.5)", (0, 255, 0)),
+            ("oklch(59.987% 0 none)", (128, 128, 128)),
+            ("color(srgb 1 0.5 0)", (255, 128, 0)),
+            ("color(srgb 100% 50% 0% / 0.5)", (255, 128, 0)),
+            (
+                "color(srgb-linear 0.2158605 0.2158605 0.2158605)",
+                (128, 128, 128),
+            ),
+        ];
+        for (s, expected) in cases {
+            let (color, _) = parse(s).expect(s)?;
+            assert_eq!(color.to_rgb8(), expected, "{s}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            "rgb(0,0,256)",
+            "rgb(0,0)",
+            "rgb(0,0,0,0,0)",
+            "rgb(0 0)",
+            "rgb(0,,0)",
+            "rgb(0 0 0 / 2)",
+            "rgb(-1 0 0)",
+            "rgb(101% 0 0)",
+            "rgb(a b c)",
+            "rgb(0 0 0",
+            "hsl(0 100% 101%)",
+            "hsl(0deg 50% -1%)",
+            "hsl(0foo 50% 50%)",
+            "hwb(0 -10% 0%)",
+            "oklab(1.5 0 0)",
+            "oklch(0.5 -0.1 0)",
+            "color(0 0 0)",
+            "color(display-p3 1 0 0)",
+            "color(srgb 2 0 0)",
+        ];
+        for s in cases {
+            assert!(parse(s).is_some_and(|result| result.is_err()), "{s}");
+        }
+
+        assert!(parse("rgb:0/0/0").is_none());
+        assert!(parse("lab(50 0 0)").is_none());
+        assert!(parse("red").is_none());
+    }
+}
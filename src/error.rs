@@ -0,0 +1,71 @@
+//! Error types.
+//!
+//! This module provides the [`LumosError`] enum, covering every way the
+//! detection can fail, so that callers can react to each failure differently.
+
+use std::io;
+use std::string::FromUtf8Error;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Result type used throughout the library.
+pub type Result<T, E = LumosError> = std::result::Result<T, E>;
+
+/// Errors that can occur while detecting the terminal background.
+#[derive(Debug, Error)]
+pub enum LumosError {
+    /// The terminal device (`/dev/tty`) cannot be opened, e.g. when running
+    /// without a controlling terminal.
+    #[error("Failed to open /dev/tty: {0}")]
+    NoTerminal(io::Error),
+
+    /// The terminal attributes cannot be read or changed, e.g. when the
+    /// device is not a terminal.
+    #[error("Failed to configure terminal: {0}")]
+    TerminalSetup(io::Error),
+
+    /// Reading from or writing to the terminal failed.
+    #[error("Failed to communicate with terminal: {0}")]
+    Io(io::Error),
+
+    /// The terminal did not answer before the deadline.
+    ///
+    /// Terminals that silently ignore OSC queries (and DA1) never send a
+    /// reply, so this is how they usually show up.
+    #[error("Terminal did not answer within {}ms", .0.as_millis())]
+    Timeout(Duration),
+
+    /// The terminal answered the DA1 sentinel but not the OSC queries, so it
+    /// does not support color queries at all.
+    #[error("Terminal does not support color queries")]
+    Unsupported,
+
+    /// The terminal response is not valid UTF-8.
+    #[error("Terminal response contained invalid UTF-8")]
+    InvalidUtf8(#[from] FromUtf8Error),
+
+    /// The terminal response does not contain any color reply.
+    #[error("No color information found in terminal response")]
+    NoReply,
+
+    /// A color string cannot be parsed.
+    #[error("Invalid color {input:?}: {reason}")]
+    InvalidColor {
+        /// The color string that was being parsed.
+        input: String,
+        /// Why it could not be parsed.
+        reason: String,
+    },
+
+    /// A background classification name cannot be parsed.
+    #[error("Invalid background {0:?}: expected dark, medium or light")]
+    InvalidBackground(String),
+
+    /// A classifier name cannot be parsed.
+    #[error("Invalid classifier {0:?}: expected luminance, cielab or oklab")]
+    InvalidClassifier(String),
+
+    /// The detection cache cannot be accessed.
+    #[error("Failed to access the cache: {0}")]
+    Cache(io::Error),
+}
@@ -1,44 +1,725 @@
 //! Terminal background color detection library.
 
+mod cache;
 mod color;
+mod colorfgbg;
+mod css;
+mod error;
 pub mod logs;
+mod names;
 mod osc;
 mod terminal;
+mod xcolor;
 
-use anyhow::{Context, Result};
+use std::env;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant, SystemTime};
 
-use color::{classify_color, luminance, parse_rgb};
-use osc::query_bg_from_terminal;
+use osc::{ColorSlot, query_colors_from_terminal};
 
-/// Detect terminal background color and determine if it's dark or light.
+pub use color::{
+    Background, Classifier, Color, DEFAULT_THRESHOLD, apca_contrast, classify_color,
+    classify_lightness, classify_luminance, contrast_ratio, luminance, parse_rgb, parse_rgba,
+};
+pub use error::{LumosError, Result};
+pub use osc::{DEFAULT_TIMEOUT, Multiplexer, Strategy, detect_multiplexers};
+
+/// Query the terminal for the given color slots and parse the replies.
+///
+/// All queries are sent in the same raw mode session, and unanswered slots
+/// are kept as `None`.
+fn query_slots(slots: &[ColorSlot], timeout: Duration) -> Result<Vec<Option<Color>>> {
+    let (replies, _) = query_colors_from_terminal(slots, timeout)?;
+    debug!("replies={replies:?}");
+
+    parse_replies(&replies)
+}
+
+/// Parse the color replies of an OSC query, keeping unanswered slots as `None`.
+fn parse_replies(replies: &[Option<String>]) -> Result<Vec<Option<Color>>> {
+    replies
+        .iter()
+        .map(|reply| reply.as_deref().map(parse_rgb).transpose())
+        .collect()
+}
+
+/// Dynamic colors reported by the terminal.
+///
+/// Terminals do not necessarily support every slot, especially the cursor and
+/// highlight ones, which are `None` when not reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalColors {
+    /// Default foreground color (OSC 10).
+    pub foreground: Option<Color>,
+    /// Default background color (OSC 11).
+    pub background: Option<Color>,
+    /// Cursor color (OSC 12).
+    pub cursor: Option<Color>,
+    /// Highlight (selection) background color (OSC 17).
+    pub highlight_background: Option<Color>,
+    /// Highlight (selection) foreground color (OSC 19).
+    pub highlight_foreground: Option<Color>,
+}
+
+/// Query the terminal for its dynamic colors.
 ///
-/// This function orchestrates the entire process:
-/// 1. Query the terminal for its background color
-/// 2. Parse the response into RGB values
-/// 3. Calculate the relative luminance
-/// 4. Determine if the background is dark or light
+/// All queries are sent in the same raw mode session, so this costs a
+/// single round-trip to the terminal.
 ///
 /// # Errors
 ///
 /// Returns an error if:
-/// - The terminal cannot be queried for its background color
-/// - The terminal's response cannot be parsed into valid RGB values
-/// - The luminance calculation fails (though this is unlikely given valid RGB input)
+/// - The terminal cannot be queried
+/// - The terminal answered no query at all, see [`LumosError::Unsupported`]
+///   and [`LumosError::Timeout`]
+/// - A color reported by the terminal cannot be parsed
+pub fn query_colors(timeout: Duration) -> Result<TerminalColors> {
+    let slots = [
+        ColorSlot::Foreground,
+        ColorSlot::Background,
+        ColorSlot::Cursor,
+        ColorSlot::HighlightBackground,
+        ColorSlot::HighlightForeground,
+    ];
+    let mut colors = query_slots(&slots, timeout)?.into_iter();
+
+    Ok(TerminalColors {
+        foreground: colors.next().flatten(),
+        background: colors.next().flatten(),
+        cursor: colors.next().flatten(),
+        highlight_background: colors.next().flatten(),
+        highlight_foreground: colors.next().flatten(),
+    })
+}
+
+/// Query the terminal for entries of its color palette.
+///
+/// Indices `0..=15` are the 16 ANSI colors, while `0..=255` covers the whole
+/// 256-color palette. All queries are sent in the same raw mode session.
+///
+/// # Returns
+///
+/// One color per index in `indices`, `None` if the terminal did not report it.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - The terminal cannot be queried
+/// - The terminal answered no query at all, see [`LumosError::Unsupported`]
+///   and [`LumosError::Timeout`]
+/// - A color reported by the terminal cannot be parsed
+pub fn query_palette(indices: RangeInclusive<u8>, timeout: Duration) -> Result<Vec<Option<Color>>> {
+    let slots: Vec<ColorSlot> = indices.map(ColorSlot::Palette).collect();
+    query_slots(&slots, timeout)
+}
+
+/// Default time a cached detection is used for, see
+/// [`detect_background_cached`].
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_mins(1);
+
+/// How a detection result was obtained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// The terminal answered OSC queries, using the given strategy.
+    Query(Strategy),
+    /// The terminal replies were read from the cache, see
+    /// [`Detector::detect_cached`].
+    Cache,
+    /// The terminal could not be queried, and the colors were read from the
+    /// `$COLORFGBG` palette indices instead, see [`Detector::detect`].
+    Colorfgbg,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query(strategy) => write!(f, "{strategy}"),
+            Self::Cache => write!(f, "cache"),
+            Self::Colorfgbg => write!(f, "colorfgbg"),
+        }
+    }
+}
+
+/// Result of a background detection, with all the intermediate data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    /// Whether the background is dark, light, or medium if a medium band is set.
+    pub background: Background,
+    /// Background color, composited over the backdrop when the terminal
+    /// reported a translucent one, see [`Detector::backdrop`].
+    pub color: Color,
+    /// Opacity of the background color reported by the terminal, from 0.0
+    /// (transparent) to 1.0 (opaque).
+    pub alpha: f64,
+    /// Foreground color, if the terminal reported it.
+    pub foreground: Option<Color>,
+    /// Raw background color reply from the terminal (e.g. `rgb:0000/0000/0000`),
+    /// or the value of `$COLORFGBG` with [`Method::Colorfgbg`].
+    pub reply: String,
+    /// Relative luminance of the background color.
+    pub luminance: f64,
+    /// Lightness of the background color, as measured by the classifier.
+    pub lightness: f64,
+    /// How the background color was obtained.
+    pub method: Method,
+    /// Time taken by the detection.
+    pub elapsed: Duration,
+}
+
+/// Configurable background detection.
+///
+/// Built with chained setters, starting from [`Detector::new`] (or
+/// [`Detector::default`]) which uses [`DEFAULT_TIMEOUT`], the default
+/// [`Classifier`] and [`DEFAULT_THRESHOLD`], without medium band nor
+/// hysteresis, and a black backdrop.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use lumos::{Background, Classifier, Detector};
+/// # use std::time::Duration;
+/// let detection = Detector::new()
+///     .timeout(Duration::from_millis(200))
+///     .classifier(Classifier::OkLab)
+///     .threshold(0.4)
+///     .hysteresis(0.05)
+///     .previous(Some(Background::Dark))
+///     .detect()?;
+/// println!("{}", detection.background);
+/// # Ok::<(), lumos::LumosError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detector {
+    timeout: Duration,
+    classifier: Classifier,
+    threshold: f64,
+    medium_band: f64,
+    hysteresis: f64,
+    previous: Option<Background>,
+    backdrop: Color,
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector {
+    /// Create a detector with default settings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            classifier: Classifier::default(),
+            threshold: DEFAULT_THRESHOLD,
+            medium_band: 0.0,
+            hysteresis: 0.0,
+            previous: None,
+            backdrop: Color::new(0, 0, 0),
+        }
+    }
+
+    /// Give up if the terminal does not answer within `timeout`.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Measure the lightness of backgrounds with `classifier`.
+    #[must_use]
+    pub fn classifier(mut self, classifier: Classifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Classify backgrounds with a lightness below `threshold` as dark.
+    #[must_use]
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Classify backgrounds with a lightness within `medium_band` of the
+    /// threshold as [`Background::Medium`], see [`classify_lightness`].
+    ///
+    /// The default band of 0.0 only classifies backgrounds as dark or light.
+    #[must_use]
+    pub fn medium_band(mut self, medium_band: f64) -> Self {
+        self.medium_band = medium_band;
+        self
+    }
+
+    /// Keep the [`previous`](Self::previous) classification when the
+    /// lightness is within `hysteresis` of it.
+    ///
+    /// This avoids flipping between classifications on tiny changes of a
+    /// background close to the threshold (or to the edges of the medium
+    /// band).
+    #[must_use]
+    pub fn hysteresis(mut self, hysteresis: f64) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Set the previous classification, used by the hysteresis band.
+    #[must_use]
+    pub fn previous(mut self, previous: Option<Background>) -> Self {
+        self.previous = previous;
+        self
+    }
+
+    /// Composite translucent backgrounds over `backdrop` before classifying
+    /// them.
+    ///
+    /// Terminals with a translucent background report its alpha, but not
+    /// what is behind the window: the desktop or the window below are
+    /// assumed to be `backdrop`, black by default.
+    #[must_use]
+    pub fn backdrop(mut self, backdrop: Color) -> Self {
+        self.backdrop = backdrop;
+        self
+    }
+
+    /// Composite `color`, with the given `alpha` opacity, over the backdrop,
+    /// see [`Color::composite`].
+    #[must_use]
+    pub fn composite(&self, color: &Color, alpha: f64) -> Color {
+        color.composite(alpha, &self.backdrop)
+    }
+
+    /// Classify a lightness, as measured by the classifier, as dark or light.
+    ///
+    /// # Returns
+    ///
+    /// - The previous classification if there is one and `lightness` is
+    ///   within `hysteresis` of the lightness range it covers
+    /// - Otherwise, the result of [`classify_lightness`] with the threshold
+    ///   and the medium band
+    #[must_use]
+    pub fn classify(&self, lightness: f64) -> Background {
+        let classify = |lightness| classify_lightness(lightness, self.threshold, self.medium_band);
+        let background = classify(lightness);
+
+        match self.previous {
+            Some(previous)
+                if previous != background
+                    && (classify(lightness - self.hysteresis) == previous
+                        || classify(lightness + self.hysteresis) == previous) =>
+            {
+                previous
+            }
+            _ => background,
+        }
+    }
+
+    /// Lightness of `color`, as measured by the classifier.
+    #[must_use]
+    pub fn lightness(&self, color: &Color) -> f64 {
+        self.classifier.lightness(color)
+    }
+
+    /// Classify a color as dark or light, see [`Self::classify`].
+    #[must_use]
+    pub fn classify_color(&self, color: &Color) -> Background {
+        self.classify(self.lightness(color))
+    }
+
+    /// Detect terminal background color and determine if it's dark or light.
+    ///
+    /// This function orchestrates the entire process:
+    /// 1. Query the terminal for its foreground and background colors
+    /// 2. Parse the responses into colors, compositing a translucent
+    ///    background over the backdrop
+    /// 3. Calculate the lightness of the background
+    /// 4. Determine if the background is dark or light, see [`Self::classify`]
+    ///
+    /// If the terminal cannot be queried, e.g. behind a multiplexer without
+    /// passthrough, the background is the palette entry of the rxvt-style
+    /// `$COLORFGBG` variable (`<fg>;<bg>` or `<fg>;<xpm>;<bg>`) if set, with
+    /// the standard xterm palette, and the method is [`Method::Colorfgbg`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The terminal cannot be queried for its background color
+    /// - The terminal does not support background queries
+    ///   ([`LumosError::Unsupported`])
+    /// - The terminal does not answer before the timeout
+    ///   ([`LumosError::Timeout`])
+    /// - The terminal's response cannot be parsed into a valid color
+    ///
+    /// Except for the last one, errors are only returned when `$COLORFGBG`
+    /// is not usable either.
+    pub fn detect(&self) -> Result<Detection> {
+        let start = Instant::now();
+
+        self.query(start)
+            .or_else(|e| self.colorfgbg(e, |name| env::var(name).ok(), start))
+    }
+
+    /// Fall back to `$COLORFGBG`, read with `var`, if the terminal could not
+    /// be queried, returning `e` otherwise.
+    fn colorfgbg(
+        &self,
+        e: LumosError,
+        var: impl Fn(&str) -> Option<String>,
+        start: Instant,
+    ) -> Result<Detection> {
+        if !matches!(
+            e,
+            LumosError::NoTerminal(_)
+                | LumosError::TerminalSetup(_)
+                | LumosError::Unsupported
+                | LumosError::Timeout(_)
+                | LumosError::NoReply
+        ) {
+            return Err(e);
+        }
+        let Some(value) = var("COLORFGBG") else {
+            return Err(e);
+        };
+        debug!("query failed ({e}), COLORFGBG={value:?}");
+        let Some((foreground, color)) = colorfgbg::parse(&value) else {
+            debug!("COLORFGBG: no background palette index");
+            return Err(e);
+        };
+        Ok(self.classified(color, 1.0, foreground, value, Method::Colorfgbg, start))
+    }
+
+    /// Query the terminal and build a detection from its replies.
+    fn query(&self, start: Instant) -> Result<Detection> {
+        let (mut replies, strategy) = query_colors_from_terminal(
+            &[ColorSlot::Foreground, ColorSlot::Background],
+            self.timeout,
+        )?;
+        debug!("replies={replies:?}");
+
+        let Some(reply) = replies[1].take() else {
+            return Err(LumosError::Unsupported);
+        };
+        self.detection(reply, replies[0].as_deref(), Method::Query(strategy), start)
+    }
+
+    /// Detect the background like [`Self::detect`], unless the current
+    /// terminal session was detected less than `ttl` ago.
+    ///
+    /// The terminal replies are cached in `$XDG_RUNTIME_DIR/lumos/`, keyed by
+    /// the tty, the terminal emulator and the multiplexer pane, so that
+    /// shell prompts can call this on every render without touching the
+    /// terminal. Cached replies are classified with the current settings.
+    ///
+    /// Terminals that do not answer are cached too, so that they do not cost
+    /// a timeout on every call: until the entry expires, the `$COLORFGBG`
+    /// fallback is used right away, or the error is returned again. A cached
+    /// timeout is ignored when the current timeout is longer, as the terminal
+    /// may just have been slow. Other errors, e.g. unparsable replies, are not
+    /// cached.
+    ///
+    /// Unless set, the previous classification (for the hysteresis) is the
+    /// cached one, even if stale. The terminal is always queried when the
+    /// session cannot be identified (without `$XDG_RUNTIME_DIR` or a tty).
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::detect`]. Failing to write the cache is not an error.
+    pub fn detect_cached(&self, ttl: Duration) -> Result<Detection> {
+        let start = Instant::now();
+
+        let Some(path) = cache::path() else {
+            debug!("cache: cannot identify the terminal session");
+            return self.detect();
+        };
+        let entry = cache::read(&path);
+        let cached_background = entry
+            .as_ref()
+            .and_then(|entry| entry.replies.as_ref().ok())
+            .map(|replies| replies.background);
+        let detector = self.clone().previous(self.previous.or(cached_background));
+
+        if let Some(entry) = entry.filter(|entry| entry.is_fresh(ttl)) {
+            debug!("cache: hit {}", path.display());
+            match entry.replies {
+                Ok(replies) => {
+                    if let Ok(detection) = detector.detection(
+                        replies.reply,
+                        replies.foreground.as_deref(),
+                        Method::Cache,
+                        start,
+                    ) {
+                        return Ok(detection);
+                    }
+                }
+                Err(failure) if failure.holds_for(self.timeout) => {
+                    return detector.colorfgbg(
+                        failure.to_error(),
+                        |name| env::var(name).ok(),
+                        start,
+                    );
+                }
+                Err(failure) => debug!("cache: {failure} is shorter than the timeout"),
+            }
+        }
+
+        debug!("cache: miss {}", path.display());
+        let result = detector.query(start);
+        let replies = match &result {
+            Ok(detection) => Some(Ok(cache::Replies {
+                background: detection.background,
+                reply: detection.reply.clone(),
+                // The alternate form keeps the 16-bit components
+                foreground: detection.foreground.as_ref().map(|fg| format!("{fg:#}")),
+            })),
+            Err(e) => cache::Failure::from_error(e).map(Err),
+        };
+        if let Some(replies) = replies {
+            let entry = cache::Entry {
+                time: SystemTime::now(),
+                replies,
+            };
+            if let Err(e) = cache::write(&path, &entry) {
+                debug!("cache: failed to write {}: {e}", path.display());
+            }
+        }
+
+        result.or_else(|e| detector.colorfgbg(e, |name| env::var(name).ok(), start))
+    }
+
+    /// Build a detection from the background and foreground replies.
+    fn detection(
+        &self,
+        reply: String,
+        foreground: Option<&str>,
+        method: Method,
+        start: Instant,
+    ) -> Result<Detection> {
+        let foreground = foreground.map(parse_rgb).transpose()?;
+        debug!("fg={foreground:?}");
+
+        let (color, alpha) = parse_rgba(&reply)?;
+        let color = self.composite(&color, alpha);
+
+        Ok(self.classified(color, alpha, foreground, reply, method, start))
+    }
+
+    /// Classify the background color of a detection.
+    fn classified(
+        &self,
+        color: Color,
+        alpha: f64,
+        foreground: Option<Color>,
+        reply: String,
+        method: Method,
+        start: Instant,
+    ) -> Detection {
+        debug!("rgb={color:?} alpha={alpha}");
+
+        let luminance = luminance(&color);
+        let lightness = self.lightness(&color);
+        debug!(
+            "lum={luminance} lightness={lightness} classifier={}",
+            self.classifier
+        );
+
+        Detection {
+            background: self.classify(lightness),
+            color,
+            alpha,
+            foreground,
+            reply,
+            luminance,
+            lightness,
+            method,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+/// Detect terminal background color and determine if it's dark or light.
+///
+/// This is [`detect_background_with_timeout`] using [`DEFAULT_TIMEOUT`].
+///
+/// # Errors
+///
+/// See [`detect_background_with_timeout`].
+pub fn detect_background() -> Result<Detection> {
+    detect_background_with_timeout(DEFAULT_TIMEOUT)
+}
+
+/// Detect terminal background color and determine if it's dark or light,
+/// giving up if the terminal does not answer within `timeout`.
+///
+/// This is [`Detector::detect`] with default settings, apart from the timeout.
+///
+/// # Errors
+///
+/// See [`Detector::detect`].
 ///
 /// # Returns
 ///
-/// - `Ok("dark")` for dark backgrounds
-/// - `Ok("light")` for light backgrounds
+/// - `Ok(Detection)` holding the classification and how it was made
 /// - `Err` if the background color cannot be determined
-pub fn detect_background() -> Result<&'static str> {
-    let reply = query_bg_from_terminal().context("Failed to query terminal background color")?;
-    debug!("reply={reply:?}");
+pub fn detect_background_with_timeout(timeout: Duration) -> Result<Detection> {
+    Detector::new().timeout(timeout).detect()
+}
+
+/// Detect terminal background color and determine if it's dark or light,
+/// reusing the result of a previous call in the same terminal session if it
+/// is at most `ttl` old.
+///
+/// This is [`Detector::detect_cached`] with default settings, see
+/// [`DEFAULT_CACHE_TTL`] for a sensible `ttl`.
+///
+/// # Errors
+///
+/// See [`Detector::detect`].
+pub fn detect_background_cached(ttl: Duration) -> Result<Detection> {
+    Detector::new().detect_cached(ttl)
+}
+
+/// Remove the cached detection of the current terminal session, e.g. after
+/// changing its theme.
+///
+/// # Errors
+///
+/// Returns [`LumosError::Cache`] if the cache entry exists but cannot be
+/// removed.
+pub fn invalidate_cache() -> Result<()> {
+    match cache::path() {
+        Some(path) => cache::remove(&path).map_err(LumosError::Cache),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_replies() -> Result<()> {
+        let replies = [
+            Some("rgb:7fff/8000/0080".to_string()),
+            None,
+            Some("rgb:ff/80/00".to_string()),
+        ];
+        assert_eq!(
+            parse_replies(&replies)?,
+            [
+                Some(Color::new(0x7fff, 0x8000, 0x0080)),
+                None,
+                Some(Color::new(0xffff, 0x8080, 0)),
+            ]
+        );
+        assert!(parse_replies(&[Some("rgb:xx/00/00".to_string())]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detector_classify() {
+        let detector = Detector::new().threshold(0.4);
+        assert_eq!(detector.classify(0.39), Background::Dark);
+        assert_eq!(detector.classify(0.4), Background::Light);
+
+        // Mid-gray is dark for luminance, but light for perceptual lightness
+        let gray = Color::from_rgb8(0x80, 0x80, 0x80);
+        assert_eq!(Detector::new().classify_color(&gray), Background::Dark);
+        for classifier in [Classifier::CieLab, Classifier::OkLab] {
+            let detector = Detector::new().classifier(classifier);
+            assert_eq!(detector.classify_color(&gray), Background::Light);
+        }
+    }
+
+    #[test]
+    fn test_detector_hysteresis() {
+        let detector = Detector::new().hysteresis(0.05);
+        // Without a previous classification, the band does not matter
+        assert_eq!(detector.classify(0.48), Background::Dark);
+        assert_eq!(detector.classify(0.52), Background::Light);
+
+        let detector = detector.previous(Some(Background::Light));
+        assert_eq!(detector.classify(0.48), Background::Light);
+        assert_eq!(detector.classify(0.44), Background::Dark);
+
+        let detector = detector.previous(Some(Background::Dark));
+        assert_eq!(detector.classify(0.52), Background::Dark);
+        assert_eq!(detector.classify(0.56), Background::Light);
+    }
+
+    #[test]
+    fn test_detector_medium_band() {
+        let detector = Detector::new().medium_band(0.1);
+        assert_eq!(detector.classify(0.39), Background::Dark);
+        assert_eq!(detector.classify(0.5), Background::Medium);
+        assert_eq!(detector.classify(0.6), Background::Light);
+
+        // Hysteresis applies to the edges of the band
+        let detector = detector.hysteresis(0.05).previous(Some(Background::Medium));
+        assert_eq!(detector.classify(0.37), Background::Medium);
+        assert_eq!(detector.classify(0.34), Background::Dark);
+        assert_eq!(detector.classify(0.63), Background::Medium);
+        let detector = detector.previous(Some(Background::Dark));
+        assert_eq!(detector.classify(0.42), Background::Dark);
+        assert_eq!(detector.classify(0.5), Background::Medium);
+    }
+
+    #[test]
+    fn test_detector_backdrop() {
+        // A half transparent light gray is dark over black, light over white
+        let gray = Color::from_rgb8(0xcc, 0xcc, 0xcc);
+        let detector = Detector::new();
+        let color = detector.composite(&gray, 0.5);
+        assert_eq!(color.to_rgb8(), (0x66, 0x66, 0x66));
+        assert_eq!(detector.classify_color(&color), Background::Dark);
+
+        let detector = detector.backdrop(Color::from_rgb8(0xff, 0xff, 0xff));
+        let color = detector.composite(&gray, 0.5);
+        assert_eq!(color.to_rgb8(), (0xe6, 0xe6, 0xe6));
+        assert_eq!(detector.classify_color(&color), Background::Light);
+
+        assert_eq!(detector.composite(&gray, 1.0), gray);
+    }
+
+    #[test]
+    fn test_detector_colorfgbg() -> Result<()> {
+        let start = Instant::now();
+        let colorfgbg = |value: &'static str| {
+            move |name: &str| (name == "COLORFGBG").then(|| value.to_string())
+        };
+        let detector = Detector::new();
+
+        let detection = detector.colorfgbg(LumosError::Unsupported, colorfgbg("15;0"), start)?;
+        assert_eq!(detection.background, Background::Dark);
+        assert_eq!(detection.color, Color::new(0, 0, 0));
+        assert_eq!(
+            detection.foreground,
+            Some(Color::new(0xffff, 0xffff, 0xffff))
+        );
+        assert_eq!(detection.reply, "15;0");
+        assert_eq!(detection.method, Method::Colorfgbg);
+
+        let timeout = LumosError::Timeout(DEFAULT_TIMEOUT);
+        let detection = detector.colorfgbg(timeout, colorfgbg("0;default;15"), start)?;
+        assert_eq!(detection.background, Background::Light);
 
-    let rgb = parse_rgb(&reply).context("Failed to parse color response from terminal")?;
-    debug!("rgb={rgb:?}");
+        // Unusable variables keep the original error
+        assert!(matches!(
+            detector.colorfgbg(LumosError::NoReply, |_| None, start),
+            Err(LumosError::NoReply)
+        ));
+        assert!(matches!(
+            detector.colorfgbg(LumosError::NoReply, colorfgbg("15;default"), start),
+            Err(LumosError::NoReply)
+        ));
 
-    let lum = luminance(&rgb);
-    debug!("lum={lum}");
+        // Terminals that did answer are not second-guessed
+        let garbled = parse_rgba("rgb:zz/0/0").unwrap_err();
+        assert!(matches!(
+            detector.colorfgbg(garbled, colorfgbg("15;0"), start),
+            Err(LumosError::InvalidColor { .. })
+        ));
 
-    Ok(classify_color(&rgb))
+        Ok(())
+    }
 }
@@ -1,162 +1,1106 @@
 //! OSC (Operating System Command) query handling for terminal communication.
 //!
 //! This module provides functions for:
-//! - Sending OSC 11 queries to request terminal background color
+//! - Sending OSC 10/11 queries to request terminal foreground/background colors
+//! - Sending OSC 4 queries to request terminal palette entries
+//! - Sending OSC 12/17/19 queries to request cursor and highlight colors
+//! - Using a Device Attributes (DA1) query as a sentinel for non-responding terminals
+//! - Detecting terminal multiplexers (tmux, screen, zellij), possibly nested
+//! - Wrapping queries in passthrough envelopes, one per multiplexer layer
+//! - Trying several query strategies until one succeeds
 //! - Reading and parsing terminal responses
 //! - Parsing OSC response formats
 
-use anyhow::{Context, Result, anyhow};
-use regex::Regex;
-use std::io::{Read, Write};
+use regex::{Regex, bytes};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
 use std::sync::LazyLock;
+use std::time::{Duration, Instant};
 
 use crate::debug;
-use crate::terminal::TerminalGuard;
+use crate::error::{LumosError, Result};
+use crate::terminal::{READ_POLL_INTERVAL, TerminalGuard};
 
-static OSC_11_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"]\s*11;([^\x07\x1b]*)").expect("Failed to compile OSC 11 regex"));
+static DA1_RE: LazyLock<bytes::Regex> =
+    LazyLock::new(|| bytes::Regex::new(r"\x1b\[\?[0-9;]*c").expect("Failed to compile DA1 regex"));
 
-/// Reads the terminal's response to the OSC 11 query.
+static OSC_REPLY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"]\s*(\d+(?:;\d+)?);([^\x07\x1b]*)").expect("Failed to compile OSC reply regex")
+});
+
+/// Primary Device Attributes query (`CSI c`).
+///
+/// Nearly every terminal answers it, and replies are sent in order, so
+/// receiving the DA1 reply means any OSC reply has already been sent.
+const DA1_QUERY: &[u8] = b"\x1b[c";
+
+/// Start of a tmux DCS passthrough envelope.
+const TMUX_PASSTHROUGH_START: &[u8] = b"\x1bPtmux;";
+
+/// Start of a screen DCS passthrough envelope.
+const SCREEN_PASSTHROUGH_START: &[u8] = b"\x1bP";
+
+/// String Terminator (ST), ending DCS sequences such as passthrough envelopes.
+const ST: &[u8] = b"\x1b\\";
+
+/// Maximum size of a single screen passthrough string (screen's own limit is 768).
+const SCREEN_MAX_CHUNK: usize = 512;
+
+/// Default amount of time to wait for the terminal to answer a query.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time to wait for the terminal when the timeout is too large to compute a
+/// deadline.
+const MAX_TIMEOUT: Duration = Duration::from_hours(24 * 365);
+
+/// Terminal color slots that can be queried with OSC sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColorSlot {
+    /// Default foreground color (OSC 10).
+    Foreground,
+    /// Default background color (OSC 11).
+    Background,
+    /// Cursor color (OSC 12).
+    Cursor,
+    /// Highlight (selection) background color (OSC 17).
+    HighlightBackground,
+    /// Highlight (selection) foreground color (OSC 19).
+    HighlightForeground,
+    /// Palette entry at the given index (OSC 4).
+    Palette(u8),
+}
+
+impl ColorSlot {
+    /// OSC parameters identifying this slot, both in queries and replies.
+    fn code(self) -> String {
+        match self {
+            Self::Foreground => "10".to_string(),
+            Self::Background => "11".to_string(),
+            Self::Cursor => "12".to_string(),
+            Self::HighlightBackground => "17".to_string(),
+            Self::HighlightForeground => "19".to_string(),
+            Self::Palette(index) => format!("4;{index}"),
+        }
+    }
+
+    /// Build the OSC query asking for this slot (e.g. `\x1b]11;?\x07` or
+    /// `\x1b]4;1;?\x07`).
+    fn query(self) -> Vec<u8> {
+        format!("\x1b]{};?\x07", self.code()).into_bytes()
+    }
+}
+
+/// Check whether a complete OSC reply (ended by BEL or ST) has been received.
+fn has_osc_terminator(buf: &[u8]) -> bool {
+    buf.contains(&b'\x07') || buf.windows(2).any(|w| w == b"\x1b\\")
+}
+
+/// Terminal multiplexers that may sit between lumos and the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    /// tmux, detected with `$TMUX`.
+    Tmux,
+    /// GNU screen, detected with `$STY`.
+    Screen,
+    /// zellij, detected with `$ZELLIJ`.
+    Zellij,
+}
+
+impl Multiplexer {
+    /// Parse a multiplexer name, as used in `$LUMOS_MULTIPLEXERS`.
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "tmux" => Some(Self::Tmux),
+            "screen" => Some(Self::Screen),
+            "zellij" => Some(Self::Zellij),
+            _ => None,
+        }
+    }
+
+    /// Wraps a payload in this multiplexer's passthrough envelope.
+    ///
+    /// zellij has no passthrough mechanism, so the payload is left untouched
+    /// and zellij has to answer the queries itself.
+    fn wrap(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Tmux => wrap_tmux_passthrough(payload),
+            Self::Screen => wrap_screen_passthrough(payload),
+            Self::Zellij => payload.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Multiplexer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tmux => write!(f, "tmux"),
+            Self::Screen => write!(f, "screen"),
+            Self::Zellij => write!(f, "zellij"),
+        }
+    }
+}
+
+/// Detects the multiplexer layers we are running in, innermost first.
+///
+/// Layers are detected with `$TMUX`, `$STY` and `$ZELLIJ`, the innermost one
+/// being guessed from `$TERM`. `$LUMOS_MULTIPLEXERS` overrides detection
+/// with a comma separated list, innermost first (e.g. `screen,tmux`).
+#[must_use]
+pub fn detect_multiplexers() -> Vec<Multiplexer> {
+    detect_multiplexers_with(|name| std::env::var(name).ok())
+}
+
+/// Detects the multiplexer layers we are running in, innermost first.
+///
+/// Multiplexers export `$TMUX`, `$STY` or `$ZELLIJ`, which are inherited by
+/// nested sessions, so these only tell which layers exist. The innermost one
+/// is guessed from `$TERM` (`tmux*` or `screen*`), the others are assumed to
+/// be nested in tmux, screen, zellij order.
+///
+/// Since nesting cannot always be guessed, `$LUMOS_MULTIPLEXERS` overrides
+/// detection with a comma separated list, innermost first (e.g.
+/// `screen,tmux` for screen running inside tmux). An empty value disables
+/// multiplexer support.
+///
+/// # Arguments
+///
+/// - `var` - Environment variable lookup
+///
+/// # Returns
+///
+/// The detected layers, innermost first, empty outside of any multiplexer.
+fn detect_multiplexers_with(var: impl Fn(&str) -> Option<String>) -> Vec<Multiplexer> {
+    if let Some(layers) = var("LUMOS_MULTIPLEXERS") {
+        return layers
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .filter_map(|name| {
+                let layer = Multiplexer::from_name(name);
+                if layer.is_none() {
+                    debug!("ignoring unknown multiplexer {name:?}");
+                }
+                layer
+            })
+            .collect();
+    }
+
+    let is_set = |name: &str| var(name).is_some_and(|v| !v.is_empty());
+    let mut layers: Vec<Multiplexer> = [
+        (Multiplexer::Tmux, "TMUX"),
+        (Multiplexer::Screen, "STY"),
+        (Multiplexer::Zellij, "ZELLIJ"),
+    ]
+    .into_iter()
+    .filter(|(_, name)| is_set(name))
+    .map(|(layer, _)| layer)
+    .collect();
+
+    let term = var("TERM").unwrap_or_default();
+    let innermost = if term.starts_with("tmux") {
+        Some(Multiplexer::Tmux)
+    } else if term.starts_with("screen") {
+        // tmux also defaults to a screen TERM, so only trust it if screen is there
+        layers
+            .iter()
+            .copied()
+            .find(|&layer| layer == Multiplexer::Screen)
+            .or(Some(Multiplexer::Tmux))
+    } else {
+        None
+    };
+
+    if let Some(innermost) = innermost
+        && let Some(pos) = layers.iter().position(|&layer| layer == innermost)
+    {
+        let layer = layers.remove(pos);
+        layers.insert(0, layer);
+    }
+
+    layers
+}
+
+/// Strategies used to get queries answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// Queries are sent as is, to be answered by the terminal or by the
+    /// innermost multiplexer.
+    Direct,
+    /// Queries are wrapped in one passthrough envelope per multiplexer layer
+    /// (innermost first), to be answered by the outer terminal.
+    Passthrough(Vec<Multiplexer>),
+}
+
+impl Strategy {
+    /// Lists the strategies worth trying for the given layers, in order.
+    ///
+    /// Direct queries come first, as they are cheap to try: multiplexers
+    /// ignoring OSC queries still answer DA1 right away. Passthrough is only
+    /// tried when some layer supports it.
+    fn candidates(layers: &[Multiplexer]) -> Vec<Self> {
+        let mut strategies = vec![Self::Direct];
+        if layers.iter().any(|&layer| layer != Multiplexer::Zellij) {
+            strategies.push(Self::Passthrough(layers.to_vec()));
+        }
+        strategies
+    }
+
+    /// Wraps a query according to this strategy.
+    ///
+    /// Bytes written go to the innermost multiplexer first, so its envelope
+    /// must be the outermost one: envelopes are applied outermost layer first.
+    fn wrap(&self, query: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Direct => query.to_vec(),
+            Self::Passthrough(layers) => layers
+                .iter()
+                .rev()
+                .fold(query.to_vec(), |payload, layer| layer.wrap(&payload)),
+        }
+    }
+
+    /// Removes envelopes that may have been added around the replies.
+    fn unwrap(&self, buf: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::Passthrough(layers) if layers.contains(&Multiplexer::Tmux) => {
+                unwrap_tmux_passthrough(&buf)
+            }
+            _ => buf,
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct => write!(f, "direct"),
+            Self::Passthrough(layers) => {
+                write!(f, "passthrough")?;
+                for (i, layer) in layers.iter().enumerate() {
+                    write!(f, "{}{layer}", if i == 0 { ":" } else { "," })?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Wraps a query in a tmux DCS passthrough envelope.
+///
+/// tmux does not forward OSC color queries to the outer terminal, unless they
+/// are wrapped as `\x1bPtmux;<query>\x1b\\`, with every ESC of the query
+/// doubled. This also requires the `allow-passthrough` tmux option to be on.
+///
+/// # Arguments
+///
+/// - `query` - Raw query bytes, possibly holding several sequences
+///
+/// # Returns
+///
+/// The query wrapped in a passthrough envelope.
+fn wrap_tmux_passthrough(query: &[u8]) -> Vec<u8> {
+    let mut wrapped = TMUX_PASSTHROUGH_START.to_vec();
+    for &byte in query {
+        if byte == b'\x1b' {
+            wrapped.push(b'\x1b');
+        }
+        wrapped.push(byte);
+    }
+    wrapped.extend_from_slice(ST);
+    wrapped
+}
+
+/// Wraps a query in screen DCS passthrough envelopes.
 ///
-/// This function reads the terminal's response to an OSC 11 query. It reads
-/// data in chunks of 64 bytes and checks for termination sequences (BEL `\x07`
-/// or ST `\x1b\\`). The function returns the complete raw response, including
-/// the escape sequence prefix and termination.
+/// screen forwards the content of `\x1bP<payload>\x1b\\` strings to the outer
+/// terminal. The payload cannot hold an ST (e.g. a nested tmux envelope), so
+/// it is split between ESC and backslash: a string ending with an ESC then
+/// reads `\x1bP...\x1b\x1b\\`, and screen forwards an ESC followed by anything
+/// but a backslash as is. Long payloads are also split, as screen limits the
+/// size of these strings.
 ///
 /// # Arguments
 ///
-/// - `terminal` - Mutable reference to the terminal guard
+/// - `payload` - Raw bytes, possibly holding several sequences
+///
+/// # Returns
+///
+/// The payload wrapped in one or more passthrough envelopes.
+fn wrap_screen_passthrough(payload: &[u8]) -> Vec<u8> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for i in 1..payload.len() {
+        if (payload[i - 1] == b'\x1b' && payload[i] == b'\\') || i - start == SCREEN_MAX_CHUNK {
+            chunks.push(&payload[start..i]);
+            start = i;
+        }
+    }
+    chunks.push(&payload[start..]);
+
+    let mut wrapped = Vec::new();
+    for chunk in chunks {
+        wrapped.extend_from_slice(SCREEN_PASSTHROUGH_START);
+        wrapped.extend_from_slice(chunk);
+        wrapped.extend_from_slice(ST);
+    }
+    wrapped
+}
+
+/// Removes tmux DCS passthrough envelopes from a terminal response.
+///
+/// Replies to passthrough queries usually come back unwrapped, but some setups
+/// wrap them the same way queries are. Inside an envelope, doubled escapes are
+/// turned back into single ones, while bytes outside envelopes are kept as is.
+///
+/// # Arguments
+///
+/// - `buf` - Raw bytes from the terminal response
+///
+/// # Returns
+///
+/// The response without any passthrough envelope.
+fn unwrap_tmux_passthrough(buf: &[u8]) -> Vec<u8> {
+    let mut unwrapped = Vec::with_capacity(buf.len());
+    let mut i = 0;
+
+    while i < buf.len() {
+        if !buf[i..].starts_with(TMUX_PASSTHROUGH_START) {
+            unwrapped.push(buf[i]);
+            i += 1;
+            continue;
+        }
+
+        i += TMUX_PASSTHROUGH_START.len();
+        while i < buf.len() {
+            match (buf[i], buf.get(i + 1)) {
+                (b'\x1b', Some(b'\x1b')) => {
+                    unwrapped.push(b'\x1b');
+                    i += 2;
+                }
+                (b'\x1b', Some(b'\\')) => {
+                    i += 2;
+                    break;
+                }
+                (byte, _) => {
+                    unwrapped.push(byte);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    unwrapped
+}
+
+/// Reads the terminal's responses to the OSC and DA1 queries.
+///
+/// This function reads data in chunks of 64 bytes until the DA1 reply
+/// (`\x1b[?...c`) shows up. Since terminals answer in order, all OSC replies
+/// are already in the buffer at that point. The function returns the complete
+/// raw response, possibly holding several replies.
+///
+/// Reads never block for long (see [`TerminalGuard::new`]), so the deadline is
+/// checked between reads and enforced with a granularity of 100ms. If the
+/// deadline is reached after an OSC reply but without a DA1 reply (terminals
+/// that do not implement DA1), the OSC reply is returned anyway.
+///
+/// A read without data only returns at the end of the polling interval, so
+/// one returning nothing right away means the terminal hung up.
+///
+/// # Arguments
+///
+/// - `terminal` - The terminal, in raw mode
+/// - `deadline` - When to give up waiting for a complete response
+/// - `timeout` - Time the deadline was set for, reported in errors
 ///
 /// # Returns
 ///
 /// - `Ok(Vec<u8>)` containing the raw terminal response
-/// - `Err` if reading from the terminal fails
-fn read_terminal_response(terminal: &mut TerminalGuard) -> Result<Vec<u8>> {
+/// - `Err(LumosError::Timeout)` if no complete response arrived before the deadline
+/// - `Err(LumosError::Io)` if reading from the terminal fails, or it hung up
+fn read_terminal_response(
+    terminal: &mut impl Read,
+    deadline: Instant,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    let poll_interval = Duration::from_millis(100 * u64::from(READ_POLL_INTERVAL));
     let mut buf = Vec::new();
 
     loop {
         let mut temp_buf = [0u8; 64];
+        let read_start = Instant::now();
         match terminal.read(&mut temp_buf) {
-            Ok(0) => {
-                debug!("got EOF");
-                break;
+            Ok(0) if read_start.elapsed() < poll_interval / 2 => {
+                debug!("got EOF, after {} bytes", buf.len());
+                return Err(LumosError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the terminal hung up",
+                )));
             }
+            Ok(0) => {}
             Ok(n) => {
                 debug!("got {n} bytes");
                 buf.extend_from_slice(&temp_buf[..n]);
-                // Check for terminator (BEL or ST)
-                if buf.contains(&b'\x07') || buf.windows(2).any(|w| w == b"\x1b\\") {
-                    debug!("got terminator");
+                if DA1_RE.is_match(&buf) {
+                    debug!("got DA1 reply");
                     break;
                 }
             }
-            Err(e) => return Err(anyhow!("Error reading from terminal: {e}")),
+            Err(e) => return Err(LumosError::Io(e)),
+        }
+
+        if Instant::now() >= deadline {
+            debug!("timed out, got {} bytes", buf.len());
+            if has_osc_terminator(&buf) {
+                debug!("got terminator, but no DA1 reply");
+                break;
+            }
+            return Err(LumosError::Timeout(timeout));
         }
     }
 
     Ok(buf)
 }
 
-/// Parses the terminal's OSC 11 response to extract color information.
+/// Extracts the values of the OSC replies in a response, by code.
+///
+/// A reply looks like `\x1b]<code>;<value>\x07` (or ST terminated), where the
+/// code holds the OSC number, and the palette index for OSC 4 (e.g. `4;1`).
+/// Codes are compared as a whole, so that e.g. `1` does not match an OSC 11
+/// reply. If a code is answered several times, the first reply wins.
+///
+/// # Arguments
+///
+/// - `response` - Decoded terminal response
+///
+/// # Returns
+///
+/// The value of each reply, keyed by code (e.g. `"11"`).
+fn parse_osc_replies(response: &str) -> HashMap<&str, &str> {
+    let mut replies = HashMap::new();
+    for caps in OSC_REPLY_RE.captures_iter(response) {
+        let (_, [code, value]) = caps.extract();
+        replies.entry(code).or_insert(value);
+    }
+    replies
+}
+
+/// Parses the terminal's OSC responses to extract color information.
 ///
 /// The terminal response typically looks like `\x1b]11;rgb:RRRR/GGGG/BBBB\x07`
-/// where RRRR, GGGG, BBBB are hexadecimal color values. This function uses
-/// a regular expression to extract the color string portion.
+/// where RRRR, GGGG, BBBB are hexadecimal color values, one per queried slot,
+/// followed by the DA1 reply (e.g. `\x1b[?62;22c`).
 ///
 /// # Arguments
 ///
 /// - `buf` - Raw bytes from the terminal response
+/// - `slots` - Queried color slots
 ///
 /// # Returns
 ///
-/// - `Ok(String)` containing the color specification (e.g., "rgb:0000/0000/0000")
-/// - `Err` if the response contains invalid UTF-8 or doesn't match expected format
-fn parse_color_response(buf: Vec<u8>) -> Result<String> {
+/// - `Ok(Vec<Option<String>>)` containing the color specification for each
+///   slot (e.g., "rgb:0000/0000/0000"), or `None` if the slot was not answered
+/// - `Err(LumosError::Unsupported)` if only the DA1 reply was received
+/// - `Err` if the response contains invalid UTF-8 or no color at all
+fn parse_color_response(buf: Vec<u8>, slots: &[ColorSlot]) -> Result<Vec<Option<String>>> {
     debug!("buf={buf:?}");
-    let response = String::from_utf8(buf).context("Terminal response contained invalid UTF-8")?;
+    let answered_da1 = DA1_RE.is_match(&buf);
+    let response = String::from_utf8(buf)?;
     debug!("response={response:?}");
 
-    let color_str = OSC_11_RE
-        .captures(&response)
-        .and_then(|caps| caps.get(1))
-        .map(|m| m.as_str().to_string())
-        .ok_or_else(|| anyhow!("No color information found in terminal response"))?;
+    let replies = parse_osc_replies(&response);
+    let colors: Vec<Option<String>> = slots
+        .iter()
+        .map(|slot| replies.get(slot.code().as_str()).map(ToString::to_string))
+        .collect();
 
-    Ok(color_str)
+    if colors.iter().all(Option::is_none) {
+        if answered_da1 {
+            return Err(LumosError::Unsupported);
+        }
+        return Err(LumosError::NoReply);
+    }
+
+    Ok(colors)
 }
 
-/// Query the terminal for its background color using OSC 11 escape sequence.
+/// Query the terminal for several colors using OSC escape sequences.
 ///
 /// This function orchestrates the complete process of querying a terminal
-/// for its background color by:
+/// for its colors by:
 /// 1. Opening the terminal device and setting raw mode
-/// 2. Sending the OSC 11 query
-/// 3. Reading and parsing the terminal's response
+/// 2. Sending one OSC query per slot, followed by a DA1 query used as a sentinel
+/// 3. Reading and parsing the terminal's responses
 /// 4. Automatically restoring terminal attributes
 ///
-/// The function sends an OSC 11 query (`\x1b]11;?\x07`) to the terminal and waits
-/// for a response. The terminal should respond with the current background color
-/// in a format like `rgb:RRRR/GGGG/BBBB` or similar.
+/// For instance, the OSC 11 query (`\x1b]11;?\x07`) asks for the background
+/// color. The terminal should respond with the current color in a format
+/// like `rgb:RRRR/GGGG/BBBB` or similar.
+///
+/// The DA1 query (`\x1b[c`) is answered by nearly every terminal, including
+/// the ones ignoring OSC queries, which turns a hang into an immediate failure.
+///
+/// Inside multiplexers, each [`Strategy`] is tried in turn until one gets an
+/// answer. Passthrough envelopes include the DA1 query: multiplexers answer
+/// DA1 on their own, which would otherwise beat the outer terminal's replies.
+/// All strategies share the same deadline, so a strategy timing out ends
+/// the query, and pending input is discarded before each new attempt, so
+/// that a late reply to the previous one is not mistaken for an answer.
+///
+/// # Arguments
+///
+/// - `slots` - Color slots to query, all in the same raw mode session
+/// - `timeout` - Maximum time to wait for the terminal to answer, whatever
+///   the number of strategies tried
 ///
 /// # Returns
 ///
-/// - `Ok(String)` containing the color response from the terminal
+/// - `Ok((Vec<Option<String>>, Strategy))` containing the color response for
+///   each slot, and the strategy that got them
+/// - `Err(LumosError::Unsupported)` if the terminal answered DA1 but no OSC query
+/// - `Err(LumosError::Timeout)` if the terminal did not answer in time
 /// - `Err` if the query fails
-pub(crate) fn query_bg_from_terminal() -> Result<String> {
+pub(crate) fn query_colors_from_terminal(
+    slots: &[ColorSlot],
+    timeout: Duration,
+) -> Result<(Vec<Option<String>>, Strategy)> {
+    let deadline = deadline_after(timeout);
     let mut terminal = TerminalGuard::new()?;
 
-    terminal
-        .write_all(b"\x1b]11;?\x07")
-        .context("Failed to write OSC 11 query to terminal")?;
-    terminal.flush()?;
+    let mut query: Vec<u8> = slots.iter().flat_map(|slot| slot.query()).collect();
+    query.extend_from_slice(DA1_QUERY);
+
+    let layers = detect_multiplexers();
+    debug!("multiplexers={layers:?}");
 
-    let buf = read_terminal_response(&mut terminal)?;
-    let color_str = parse_color_response(buf)?;
+    let mut strategies = Strategy::candidates(&layers).into_iter().peekable();
+    while let Some(strategy) = strategies.next() {
+        debug!("trying {strategy} strategy");
+        match query_with_strategy(&mut terminal, &strategy, &query, slots, deadline, timeout) {
+            Ok(colors) => {
+                debug!("{strategy} strategy succeeded");
+                return Ok((colors, strategy));
+            }
+            Err(e @ LumosError::Unsupported) if strategies.peek().is_some() => {
+                debug!("{strategy} strategy failed: {e}");
+                terminal.discard_input().map_err(LumosError::Io)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
 
-    Ok(color_str)
+    unreachable!("there is always at least one strategy")
+}
+
+/// The deadline `timeout` from now, or [`MAX_TIMEOUT`] from now if that
+/// cannot be represented.
+fn deadline_after(timeout: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_add(timeout)
+        .unwrap_or_else(|| now + MAX_TIMEOUT)
+}
+
+/// Sends a query according to a strategy, then reads and parses the replies.
+fn query_with_strategy(
+    terminal: &mut TerminalGuard,
+    strategy: &Strategy,
+    query: &[u8],
+    slots: &[ColorSlot],
+    deadline: Instant,
+    timeout: Duration,
+) -> Result<Vec<Option<String>>> {
+    terminal
+        .write_all(&strategy.wrap(query))
+        .map_err(LumosError::Io)?;
+    terminal.flush().map_err(LumosError::Io)?;
+
+    let buf = read_terminal_response(terminal, deadline, timeout)?;
+    parse_color_response(strategy.unwrap(buf), slots)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
+
+    /// Terminal replaying chunks of bytes, `None` being a read without data.
+    ///
+    /// Once all chunks are read, it hangs up if `hangup` is set, and never
+    /// sends anything else otherwise.
+    struct FakeTerminal {
+        chunks: Vec<Option<&'static [u8]>>,
+        hangup: bool,
+    }
+
+    impl FakeTerminal {
+        fn new(chunks: &[Option<&'static [u8]>], hangup: bool) -> Self {
+            Self {
+                chunks: chunks.iter().rev().copied().collect(),
+                hangup,
+            }
+        }
+    }
+
+    impl Read for FakeTerminal {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop() {
+                Some(Some(chunk)) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(chunk.len())
+                }
+                None if self.hangup => Ok(0),
+                Some(None) | None => {
+                    thread::sleep(Duration::from_millis(100 * u64::from(READ_POLL_INTERVAL)));
+                    Ok(0)
+                }
+            }
+        }
+    }
+
+    /// Parse a response to a background-only query.
+    fn parse_bg(buf: &[u8]) -> Result<String> {
+        let mut colors = parse_color_response(buf.to_vec(), &[ColorSlot::Background])?;
+        colors.pop().flatten().ok_or(LumosError::NoReply)
+    }
 
     #[test]
     fn test_parse_color_response() -> Result<()> {
         // Test standard OSC 11 response
-        let response = b"\x1b]11;rgb:0000/0000/0000\x07".to_vec();
-        assert_eq!(parse_color_response(response)?, "rgb:0000/0000/0000");
+        let response = b"\x1b]11;rgb:0000/0000/0000\x07";
+        assert_eq!(parse_bg(response)?, "rgb:0000/0000/0000");
 
         // Test with whitespace
-        let response = b"\x1b] 11;rgb:ffff/8000/0000\x07".to_vec();
-        assert_eq!(parse_color_response(response)?, "rgb:ffff/8000/0000");
+        let response = b"\x1b] 11;rgb:ffff/8000/0000\x07";
+        assert_eq!(parse_bg(response)?, "rgb:ffff/8000/0000");
 
         // Test with ST terminator
-        let response = b"\x1b]11;rgb:1234/5678/9abc\x1b\\".to_vec();
-        assert_eq!(parse_color_response(response)?, "rgb:1234/5678/9abc");
+        let response = b"\x1b]11;rgb:1234/5678/9abc\x1b\\";
+        assert_eq!(parse_bg(response)?, "rgb:1234/5678/9abc");
 
         // Test hex format
-        let response = b"\x1b]11;#ff8000\x07".to_vec();
-        assert_eq!(parse_color_response(response)?, "#ff8000");
+        let response = b"\x1b]11;#ff8000\x07";
+        assert_eq!(parse_bg(response)?, "#ff8000");
 
         // Test invalid response
-        let response = b"\x1b]10;rgb:0000/0000/0000\x07".to_vec(); // Wrong OSC number
-        assert!(parse_color_response(response).is_err());
+        let response = b"\x1b]10;rgb:0000/0000/0000\x07"; // Wrong OSC number
+        assert!(parse_bg(response).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_color_response_with_da1() -> Result<()> {
+        // Test OSC 11 reply followed by DA1 reply
+        let response = b"\x1b]11;rgb:0000/0000/0000\x07\x1b[?62;22c";
+        assert_eq!(parse_bg(response)?, "rgb:0000/0000/0000");
+
+        // Test with ST terminator
+        let response = b"\x1b]11;rgb:1234/5678/9abc\x1b\\\x1b[?1;2c";
+        assert_eq!(parse_bg(response)?, "rgb:1234/5678/9abc");
+
+        // Test DA1 reply without OSC 11 reply
+        let response = b"\x1b[?65;1;9c";
+        let err = parse_bg(response).unwrap_err();
+        assert!(matches!(err, LumosError::Unsupported));
+
+        // Test DA1 reply without parameters
+        let response = b"\x1b[?c";
+        let err = parse_bg(response).unwrap_err();
+        assert!(matches!(err, LumosError::Unsupported));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_color_response_several_slots() -> Result<()> {
+        let slots = [ColorSlot::Foreground, ColorSlot::Background];
+
+        // Test both replies, followed by DA1 reply
+        let response = b"\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:0000/0000/0000\x07\x1b[?62c";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [
+                Some("rgb:ffff/ffff/ffff".to_string()),
+                Some("rgb:0000/0000/0000".to_string())
+            ]
+        );
+
+        // Test replies in another order, with ST terminators
+        let response = b"\x1b]11;#000000\x1b\\\x1b]10;#ffffff\x1b\\";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [Some("#ffffff".to_string()), Some("#000000".to_string())]
+        );
+
+        // Test missing foreground reply
+        let response = b"\x1b]11;rgb:0000/0000/0000\x07\x1b[?62c";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [None, Some("rgb:0000/0000/0000".to_string())]
+        );
+
+        // Test no reply at all
+        let response = b"\x1b[?62c";
+        let err = parse_color_response(response.to_vec(), &slots).unwrap_err();
+        assert!(matches!(err, LumosError::Unsupported));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_color_response_palette() -> Result<()> {
+        let slots = [
+            ColorSlot::Palette(0),
+            ColorSlot::Palette(1),
+            ColorSlot::Palette(10),
+        ];
+
+        let response = b"\x1b]4;0;rgb:0000/0000/0000\x07\x1b]4;1;rgb:cdcd/0000/0000\x07\x1b]4;10;rgb:0000/ffff/0000\x07\x1b[?62c";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [
+                Some("rgb:0000/0000/0000".to_string()),
+                Some("rgb:cdcd/0000/0000".to_string()),
+                Some("rgb:0000/ffff/0000".to_string())
+            ]
+        );
+
+        // Test partial palette, index 1 must not match index 10
+        let response = b"\x1b]4;10;rgb:0000/ffff/0000\x1b\\\x1b[?62c";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [None, None, Some("rgb:0000/ffff/0000".to_string())]
+        );
 
         Ok(())
     }
 
+    #[test]
+    fn test_parse_color_response_cursor_and_highlight() -> Result<()> {
+        let slots = [
+            ColorSlot::Cursor,
+            ColorSlot::HighlightBackground,
+            ColorSlot::HighlightForeground,
+        ];
+
+        // Test terminal supporting cursor color only
+        let response = b"\x1b]12;rgb:ffff/cccc/0000\x07\x1b[?62c";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [Some("rgb:ffff/cccc/0000".to_string()), None, None]
+        );
+
+        // Test all slots answered
+        let response = b"\x1b]12;#ffcc00\x07\x1b]17;#44475a\x07\x1b]19;#f8f8f2\x07\x1b[?62c";
+        assert_eq!(
+            parse_color_response(response.to_vec(), &slots)?,
+            [
+                Some("#ffcc00".to_string()),
+                Some("#44475a".to_string()),
+                Some("#f8f8f2".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_slot_query() {
+        assert_eq!(ColorSlot::Foreground.query(), b"\x1b]10;?\x07");
+        assert_eq!(ColorSlot::Background.query(), b"\x1b]11;?\x07");
+        assert_eq!(ColorSlot::Cursor.query(), b"\x1b]12;?\x07");
+        assert_eq!(ColorSlot::HighlightBackground.query(), b"\x1b]17;?\x07");
+        assert_eq!(ColorSlot::HighlightForeground.query(), b"\x1b]19;?\x07");
+        assert_eq!(ColorSlot::Palette(255).query(), b"\x1b]4;255;?\x07");
+    }
+
+    #[test]
+    fn test_parse_osc_replies() {
+        let response = "\x1b]11;rgb:0000/0000/0000\x07\x1b]1;title\x07\
+                        \x1b]4;1;rgb:cdcd/0000/0000\x1b\\\x1b]11;#ffffff\x07";
+        let replies = parse_osc_replies(response);
+        assert_eq!(replies.get("11"), Some(&"rgb:0000/0000/0000"));
+        assert_eq!(replies.get("1"), Some(&"title"));
+        assert_eq!(replies.get("4;1"), Some(&"rgb:cdcd/0000/0000"));
+        assert_eq!(replies.get("4"), None);
+        assert_eq!(replies.get("10"), None);
+        assert_eq!(replies.get("111"), None);
+    }
+
+    #[test]
+    fn test_wrap_tmux_passthrough() {
+        assert_eq!(
+            wrap_tmux_passthrough(b"\x1b]11;?\x07"),
+            b"\x1bPtmux;\x1b\x1b]11;?\x07\x1b\\"
+        );
+        assert_eq!(
+            wrap_tmux_passthrough(b"\x1b]10;?\x07\x1b]11;?\x07\x1b[c"),
+            b"\x1bPtmux;\x1b\x1b]10;?\x07\x1b\x1b]11;?\x07\x1b\x1b[c\x1b\\"
+        );
+        assert_eq!(wrap_tmux_passthrough(b""), b"\x1bPtmux;\x1b\\");
+    }
+
+    #[test]
+    fn test_wrap_screen_passthrough() {
+        assert_eq!(
+            wrap_screen_passthrough(b"\x1b]11;?\x07"),
+            b"\x1bP\x1b]11;?\x07\x1b\\"
+        );
+
+        // Embedded ST is split between ESC and backslash
+        assert_eq!(
+            wrap_screen_passthrough(b"\x1b]11;?\x1b\\"),
+            b"\x1bP\x1b]11;?\x1b\x1b\\\x1bP\\\x1b\\"
+        );
+
+        // Long payloads are split in several strings
+        let payload = vec![b'x'; SCREEN_MAX_CHUNK + 1];
+        let wrapped = wrap_screen_passthrough(&payload);
+        assert_eq!(
+            wrapped.len(),
+            payload.len() + 2 * (SCREEN_PASSTHROUGH_START.len() + ST.len())
+        );
+        assert!(wrapped.ends_with(b"\x1bPx\x1b\\"));
+    }
+
+    #[test]
+    fn test_detect_multiplexers() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| (*v).to_string())
+            }
+        };
+
+        assert!(detect_multiplexers_with(env(&[("TERM", "xterm-256color")])).is_empty());
+        assert_eq!(
+            detect_multiplexers_with(env(&[("TMUX", "/tmp/tmux-1000/default,42,0")])),
+            [Multiplexer::Tmux]
+        );
+        assert_eq!(
+            detect_multiplexers_with(env(&[("ZELLIJ", "0")])),
+            [Multiplexer::Zellij]
+        );
+        assert!(detect_multiplexers_with(env(&[("TMUX", "")])).is_empty());
+
+        // screen inside tmux
+        assert_eq!(
+            detect_multiplexers_with(env(&[
+                ("TMUX", "/tmp/tmux-1000/default,42,0"),
+                ("STY", "1234.pts-0.host"),
+                ("TERM", "screen.xterm-256color"),
+            ])),
+            [Multiplexer::Screen, Multiplexer::Tmux]
+        );
+
+        // tmux inside screen
+        assert_eq!(
+            detect_multiplexers_with(env(&[
+                ("TMUX", "/tmp/tmux-1000/default,42,0"),
+                ("STY", "1234.pts-0.host"),
+                ("TERM", "tmux-256color"),
+            ])),
+            [Multiplexer::Tmux, Multiplexer::Screen]
+        );
+
+        // tmux with its default screen TERM
+        assert_eq!(
+            detect_multiplexers_with(env(&[
+                ("TMUX", "/tmp/tmux-1000/default,42,0"),
+                ("TERM", "screen"),
+            ])),
+            [Multiplexer::Tmux]
+        );
+
+        // Explicit layers
+        assert_eq!(
+            detect_multiplexers_with(env(&[
+                ("TMUX", "/tmp/tmux-1000/default,42,0"),
+                ("STY", "1234.pts-0.host"),
+                ("LUMOS_MULTIPLEXERS", "tmux, Screen,nope"),
+            ])),
+            [Multiplexer::Tmux, Multiplexer::Screen]
+        );
+        assert!(
+            detect_multiplexers_with(env(&[
+                ("TMUX", "/tmp/tmux-1000/default,42,0"),
+                ("LUMOS_MULTIPLEXERS", ""),
+            ]))
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_strategy() {
+        assert_eq!(Strategy::candidates(&[]), [Strategy::Direct]);
+        assert_eq!(
+            Strategy::candidates(&[Multiplexer::Zellij]),
+            [Strategy::Direct]
+        );
+        assert_eq!(
+            Strategy::candidates(&[Multiplexer::Tmux]),
+            [
+                Strategy::Direct,
+                Strategy::Passthrough(vec![Multiplexer::Tmux])
+            ]
+        );
+
+        let query = b"\x1b]11;?\x07";
+        assert_eq!(Strategy::Direct.wrap(query), query);
+
+        // screen inside tmux: screen envelope around tmux envelope
+        let strategy = Strategy::Passthrough(vec![Multiplexer::Screen, Multiplexer::Tmux]);
+        assert_eq!(
+            strategy.wrap(query),
+            b"\x1bP\x1bPtmux;\x1b\x1b]11;?\x07\x1b\x1b\\\x1bP\\\x1b\\"
+        );
+
+        // tmux inside screen: tmux envelope around screen envelope
+        let strategy = Strategy::Passthrough(vec![Multiplexer::Tmux, Multiplexer::Screen]);
+        assert_eq!(
+            strategy.wrap(query),
+            b"\x1bPtmux;\x1b\x1bP\x1b\x1b]11;?\x07\x1b\x1b\\\x1b\\"
+        );
+
+        assert_eq!(Strategy::Direct.to_string(), "direct");
+        assert_eq!(strategy.to_string(), "passthrough:tmux,screen");
+    }
+
+    #[test]
+    fn test_unwrap_tmux_passthrough() {
+        // Replies to passthrough queries usually come back as is
+        let response = b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\\x1b[?62;22c";
+        assert_eq!(unwrap_tmux_passthrough(response), response);
+
+        // Wrapped replies, with a doubled ST inside the envelope
+        let response = b"\x1bPtmux;\x1b\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\x1b\\\x1b\\";
+        assert_eq!(
+            unwrap_tmux_passthrough(response),
+            b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\"
+        );
+
+        // Wrapped OSC reply followed by an unwrapped DA1 reply
+        let response = b"\x1bPtmux;\x1b\x1b]11;rgb:0000/0000/0000\x07\x1b\\\x1b[?1;2c";
+        assert_eq!(
+            unwrap_tmux_passthrough(response),
+            b"\x1b]11;rgb:0000/0000/0000\x07\x1b[?1;2c"
+        );
+
+        // Truncated envelope
+        let response = b"\x1bPtmux;\x1b\x1b]11;rgb:00";
+        assert_eq!(unwrap_tmux_passthrough(response), b"\x1b]11;rgb:00");
+
+        // Round trip
+        let query = b"\x1b]10;?\x07\x1b]11;?\x1b\\\x1b[c";
+        assert_eq!(
+            unwrap_tmux_passthrough(&wrap_tmux_passthrough(query)),
+            query
+        );
+    }
+
+    #[test]
+    fn test_parse_color_response_tmux() -> Result<()> {
+        let slots = [ColorSlot::Foreground, ColorSlot::Background];
+
+        let response = unwrap_tmux_passthrough(
+            b"\x1bPtmux;\x1b\x1b]10;rgb:cdcd/d6d6/f4f4\x1b\x1b\\\x1b\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\x1b\\\x1b\x1b[?62;22c\x1b\\",
+        );
+        assert_eq!(
+            parse_color_response(response, &slots)?,
+            [
+                Some("rgb:cdcd/d6d6/f4f4".to_string()),
+                Some("rgb:1e1e/1e1e/2e2e".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_terminal_response() -> Result<()> {
+        let timeout = Duration::from_millis(250);
+
+        // The DA1 reply ends the response, even split across reads
+        let mut terminal = FakeTerminal::new(
+            &[
+                Some(b"\x1b]11;rgb:0000/0000/0000\x07"),
+                None,
+                Some(b"\x1b[?62"),
+                Some(b";22c"),
+                Some(b"\x1b]10;rgb:ffff/ffff/ffff\x07"),
+            ],
+            false,
+        );
+        assert_eq!(
+            read_terminal_response(&mut terminal, Instant::now() + timeout, timeout)?,
+            b"\x1b]11;rgb:0000/0000/0000\x07\x1b[?62;22c"
+        );
+
+        // Without DA1 reply, a complete OSC reply is kept at the deadline
+        let mut terminal = FakeTerminal::new(&[Some(b"\x1b]11;rgb:0000/0000/0000\x1b\\")], false);
+        assert_eq!(
+            read_terminal_response(&mut terminal, Instant::now() + timeout, timeout)?,
+            b"\x1b]11;rgb:0000/0000/0000\x1b\\"
+        );
+
+        // Otherwise the deadline is an error
+        let mut terminal = FakeTerminal::new(&[], false);
+        let start = Instant::now();
+        assert!(matches!(
+            read_terminal_response(&mut terminal, Instant::now() + timeout, timeout),
+            Err(LumosError::Timeout(_))
+        ));
+        assert!(start.elapsed() >= timeout);
+
+        let mut terminal = FakeTerminal::new(&[Some(b"\x1b]11;rgb:0000/00")], false);
+        assert!(matches!(
+            read_terminal_response(&mut terminal, Instant::now() + timeout, timeout),
+            Err(LumosError::Timeout(_))
+        ));
+
+        // Hangups fail right away
+        let mut terminal = FakeTerminal::new(&[Some(b"\x1b]11;rgb:0000/00")], true);
+        let start = Instant::now();
+        assert!(matches!(
+            read_terminal_response(&mut terminal, Instant::now() + timeout, timeout),
+            Err(LumosError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        assert!(start.elapsed() < timeout);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline_after() {
+        let now = Instant::now();
+        let deadline = deadline_after(Duration::from_millis(500));
+        assert!(deadline >= now + Duration::from_millis(500));
+
+        let deadline = deadline_after(Duration::MAX);
+        assert!(deadline >= now + MAX_TIMEOUT);
+    }
+
+    #[test]
+    fn test_has_osc_terminator() {
+        assert!(has_osc_terminator(b"\x1b]11;rgb:0000/0000/0000\x07"));
+        assert!(has_osc_terminator(b"\x1b]11;rgb:0000/0000/0000\x1b\\"));
+        assert!(!has_osc_terminator(b"\x1b]11;rgb:0000/00"));
+        assert!(!has_osc_terminator(b""));
+    }
+
     #[test]
     fn test_parse_color_response_edge_cases() {
         // Test empty response
-        let response = b"".to_vec();
-        assert!(parse_color_response(response).is_err());
+        let response = b"";
+        assert!(parse_bg(response).is_err());
 
         // Test invalid UTF-8
-        let response = vec![0xff, 0xfe, 0xfd];
-        assert!(parse_color_response(response).is_err());
+        let response = [0xff, 0xfe, 0xfd];
+        assert!(parse_bg(&response).is_err());
 
         // Test malformed response
-        let response = b"garbage data".to_vec();
-        assert!(parse_color_response(response).is_err());
+        let response = b"garbage data";
+        assert!(parse_bg(response).is_err());
     }
 }
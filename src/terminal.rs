@@ -5,13 +5,16 @@
 //! - Setting up raw mode for direct character input
 //! - Automatic cleanup and restoration of terminal state via RAII guard
 
-use anyhow::{Context, Result};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
-use termios::{ECHO, ICANON, TCSANOW, Termios, tcsetattr};
+use termios::{ECHO, ICANON, TCIFLUSH, TCSANOW, Termios, VMIN, VTIME, tcflush, tcsetattr};
 
 use crate::debug;
+use crate::error::{LumosError, Result};
+
+/// Maximum time a single read may block, in tenths of a second (`VTIME` unit).
+pub(crate) const READ_POLL_INTERVAL: u8 = 1;
 
 /// RAII guard for terminal raw mode that automatically restores terminal state on drop.
 ///
@@ -32,32 +35,45 @@ impl TerminalGuard {
     /// 1. Opens `/dev/tty` with read and write permissions
     /// 2. Saves the current terminal attributes
     /// 3. Sets the terminal to raw mode (disables canonical input and echo)
+    /// 4. Makes reads non-blocking past [`READ_POLL_INTERVAL`]
+    ///
+    /// Since `VMIN` is 0, a read returns `Ok(0)` when no byte arrived during the
+    /// polling interval, letting callers enforce their own deadline.
     ///
     /// The terminal will be automatically restored when the guard is dropped.
     ///
     /// # Returns
     ///
     /// - `Ok(TerminalGuard)` ready for direct terminal communication
-    /// - `Err` if the terminal cannot be opened or configured
+    /// - `Err(LumosError::NoTerminal)` if the terminal cannot be opened
+    /// - `Err(LumosError::TerminalSetup)` if the terminal cannot be configured
     pub(crate) fn new() -> Result<Self> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .open("/dev/tty")
-            .context("Failed to open /dev/tty")?;
+            .map_err(LumosError::NoTerminal)?;
 
         let fd = file.as_raw_fd();
-        let original_termios = Termios::from_fd(fd).context("Failed to get terminal attributes")?;
+        let original_termios = Termios::from_fd(fd).map_err(LumosError::TerminalSetup)?;
 
         let mut new_termios = original_termios;
         new_termios.c_lflag &= !(ICANON | ECHO);
-        tcsetattr(fd, TCSANOW, &new_termios).context("Failed to set terminal to raw mode")?;
+        new_termios.c_cc[VMIN] = 0;
+        new_termios.c_cc[VTIME] = READ_POLL_INTERVAL;
+        tcsetattr(fd, TCSANOW, &new_termios).map_err(LumosError::TerminalSetup)?;
 
         Ok(Self {
             file,
             original_termios,
         })
     }
+
+    /// Discards the input received but not read yet, e.g. late replies to a
+    /// previous query.
+    pub(crate) fn discard_input(&self) -> std::io::Result<()> {
+        tcflush(self.file.as_raw_fd(), TCIFLUSH)
+    }
 }
 
 impl Drop for TerminalGuard {
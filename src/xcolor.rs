@@ -0,0 +1,441 @@
+//! X11 color specifications, as understood by `XParseColor`.
+//!
+//! Terminals derived from xterm answer color queries with the X11 syntax,
+//! usually `rgb:RRRR/GGGG/BBBB`, but any of the device-independent forms of
+//! the Xcms library may be used, and show up in configuration files:
+//!
+//! - `rgb:R/G/B` - 1 to 4 hex digits per component, scaled to 16 bits
+//! - `rgbi:R/G/B` - intensities between 0.0 and 1.0, mapped linearly
+//! - `CIEXYZ:X/Y/Z`, `CIEuvY:u/v/Y`, `CIExyY:x/y/Y`
+//! - `CIELab:L/a/b`, `CIELuv:L/u/v`
+//! - `TekHVC:H/V/C` - Tektronix hue (degrees), value and chroma
+//!
+//! Prefixes are case-insensitive. The CIE forms are relative to the D65
+//! white point, and colors outside of the sRGB gamut are clipped.
+
+use std::ops::RangeInclusive;
+
+use crate::color::{Color, D65, invalid_color, scale_unit};
+use crate::error::Result;
+
+/// Parse an X11 color specification, and its alpha (1.0 when opaque).
+///
+/// Returns `None` when `s` does not start with a known prefix, so that other
+/// formats can be tried.
+pub(crate) fn parse(s: &str) -> Option<Result<(Color, f64)>> {
+    let (prefix, spec) = s.split_once(':')?;
+
+    let result = match prefix.to_ascii_lowercase().as_str() {
+        // `rgba:` and a fourth (alpha) component are not X11, but some
+        // terminals answer that way
+        "rgb" | "rgba" => return Some(parse_hex(s, spec)),
+        "rgbi" => floats(s, spec, ["red", "green", "blue"]).and_then(|[r, g, b]| {
+            let r = check(s, "red", r, 0.0..=1.0)?;
+            let g = check(s, "green", g, 0.0..=1.0)?;
+            let b = check(s, "blue", b, 0.0..=1.0)?;
+            // Without a characterized screen, Xcms maps intensities
+            // linearly to device values
+            Ok(Color::new(scale_unit(r), scale_unit(g), scale_unit(b)))
+        }),
+        "ciexyz" => floats(s, spec, ["X", "Y", "Z"]).and_then(|[x, y, z]| {
+            let x = check(s, "X", x, 0.0..=f64::INFINITY)?;
+            let y = check(s, "Y", y, 0.0..=1.0)?;
+            let z = check(s, "Z", z, 0.0..=f64::INFINITY)?;
+            Ok(Color::from_xyz(x, y, z))
+        }),
+        "cieuvy" => floats(s, spec, ["u", "v", "Y"]).and_then(|[u, v, y]| {
+            let y = check(s, "Y", y, 0.0..=1.0)?;
+            from_uvy(s, u, v, y)
+        }),
+        "ciexyy" => floats(s, spec, ["x", "y", "Y"]).and_then(|[x, y, luminance]| {
+            let luminance = check(s, "Y", luminance, 0.0..=1.0)?;
+            from_xyy(s, x, y, luminance)
+        }),
+        "cielab" => floats(s, spec, ["L", "a", "b"]).and_then(|[l, a, b]| {
+            let l = check(s, "L", l, 0.0..=100.0)?;
+            Ok(from_lab(l, a, b))
+        }),
+        "cieluv" => floats(s, spec, ["L", "u", "v"]).and_then(|[l, u, v]| {
+            let l = check(s, "L", l, 0.0..=100.0)?;
+            from_luv(s, l, u, v)
+        }),
+        "tekhvc" => floats(s, spec, ["H", "V", "C"]).and_then(|[h, v, c]| {
+            let h = check(s, "H", h, 0.0..=360.0)?;
+            let v = check(s, "V", v, 0.0..=100.0)?;
+            let c = check(s, "C", c, 0.0..=f64::INFINITY)?;
+            from_tekhvc(s, h, v, c)
+        }),
+        _ => return None,
+    };
+
+    // Only `rgb:` and `rgba:` have an alpha component
+    Some(result.map(|color| (color, 1.0)))
+}
+
+/// Parse the `R/G/B[/A]` hex components of an `rgb:` specification.
+fn parse_hex(s: &str, spec: &str) -> Result<(Color, f64)> {
+    let parts: Vec<&str> = spec.split('/').collect();
+    if parts.len() != 3 && parts.len() != 4 {
+        return Err(invalid_color(
+            s,
+            format!("expected 3 or 4 components, got {}", parts.len()),
+        ));
+    }
+
+    let r = hex_to_u16(parts[0]).map_err(|e| invalid_color(s, format!("red component: {e}")))?;
+    let g = hex_to_u16(parts[1]).map_err(|e| invalid_color(s, format!("green component: {e}")))?;
+    let b = hex_to_u16(parts[2]).map_err(|e| invalid_color(s, format!("blue component: {e}")))?;
+    let alpha = match parts.get(3) {
+        Some(alpha) => {
+            hex_to_u16(alpha).map_err(|e| invalid_color(s, format!("alpha component: {e}")))?
+        }
+        None => u16::MAX,
+    };
+    Ok((Color::new(r, g, b), f64::from(alpha) / 65535.0))
+}
+
+/// Convert a hex component of 1 to 4 digits to 16 bits.
+///
+/// Like `XParseColor`, values are scaled rather than shifted, so that the
+/// largest value of any width is `0xffff`: `f`, `ff`, `fff` and `ffff` are
+/// all full intensity, and `80` becomes `0x8080`.
+///
+/// # Arguments
+///
+/// * `hex` - Hex string without '0x' prefix
+///
+/// # Returns
+///
+/// - `Ok(u16)` - The converted value
+/// - `Err(String)` - Why the string is not valid hex
+pub(crate) fn hex_to_u16(hex: &str) -> Result<u16, String> {
+    if hex.is_empty() || hex.len() > 4 {
+        return Err(format!(
+            "invalid hex length: expected 1 to 4 characters, got {}",
+            hex.len()
+        ));
+    }
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex string {hex:?}"));
+    }
+    let n = u32::from_str_radix(hex, 16).map_err(|e| format!("invalid hex string {hex:?}: {e}"))?;
+    let max = (1u32 << (4 * hex.len())) - 1;
+
+    #[allow(clippy::cast_possible_truncation)]
+    Ok((n * 65535 / max) as u16)
+}
+
+/// Parse the three `/`-separated floating point components of `spec`.
+///
+/// `names` are used in error messages.
+fn floats(s: &str, spec: &str, names: [&str; 3]) -> Result<[f64; 3]> {
+    let parts: Vec<&str> = spec.split('/').collect();
+    if parts.len() != 3 {
+        return Err(invalid_color(
+            s,
+            format!("expected 3 components, got {}", parts.len()),
+        ));
+    }
+
+    let mut values = [0.0; 3];
+    for ((value, part), name) in values.iter_mut().zip(parts).zip(names) {
+        *value = part
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| {
+                invalid_color(s, format!("{name} component: invalid number {part:?}"))
+            })?;
+    }
+    Ok(values)
+}
+
+/// Check that the `name` component of `s` is within `range`.
+pub(crate) fn check(s: &str, name: &str, value: f64, range: RangeInclusive<f64>) -> Result<f64> {
+    if range.contains(&value) {
+        Ok(value)
+    } else if range.end().is_infinite() {
+        Err(invalid_color(
+            s,
+            format!("{name} component: {value} is negative"),
+        ))
+    } else {
+        Err(invalid_color(
+            s,
+            format!(
+                "{name} component: {value} is out of range {}-{}",
+                range.start(),
+                range.end()
+            ),
+        ))
+    }
+}
+
+/// CIE 1976 u'v' chromaticity of the D65 white point.
+fn white_uv() -> (f64, f64) {
+    let (x, y, z) = D65;
+    let d = x + 15.0 * y + 3.0 * z;
+    (4.0 * x / d, 9.0 * y / d)
+}
+
+/// Convert CIE 1976 u'v' chromaticity and luminance to a color.
+fn from_uvy(s: &str, u: f64, v: f64, y: f64) -> Result<Color> {
+    if y == 0.0 {
+        return Ok(Color::new(0, 0, 0));
+    }
+    if v <= 0.0 {
+        return Err(invalid_color(s, "v component: must be positive"));
+    }
+    Ok(Color::from_xyz(
+        y * 9.0 * u / (4.0 * v),
+        y,
+        y * (12.0 - 3.0 * u - 20.0 * v) / (4.0 * v),
+    ))
+}
+
+/// Convert CIE 1931 xy chromaticity and luminance to a color.
+fn from_xyy(s: &str, x: f64, y: f64, luminance: f64) -> Result<Color> {
+    if luminance == 0.0 {
+        return Ok(Color::new(0, 0, 0));
+    }
+    if y <= 0.0 {
+        return Err(invalid_color(s, "y component: must be positive"));
+    }
+    Ok(Color::from_xyz(
+        x * luminance / y,
+        luminance,
+        (1.0 - x - y) * luminance / y,
+    ))
+}
+
+/// Relative luminance of a CIE lightness `L*` between 0 and 100.
+fn lightness_to_y(l: f64) -> f64 {
+    if l > 8.0 {
+        ((l + 16.0) / 116.0).powi(3)
+    } else {
+        l * 27.0 / 24389.0
+    }
+}
+
+/// Convert CIE 1976 L\*a\*b\* coordinates to a color.
+fn from_lab(l: f64, a: f64, b: f64) -> Color {
+    const DELTA: f64 = 6.0 / 29.0;
+
+    let expand = |t: f64| {
+        if t > DELTA {
+            t.powi(3)
+        } else {
+            3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
+        }
+    };
+
+    let fy = (l + 16.0) / 116.0;
+    Color::from_xyz(
+        D65.0 * expand(fy + a / 500.0),
+        D65.1 * lightness_to_y(l),
+        D65.2 * expand(fy - b / 200.0),
+    )
+}
+
+/// Convert CIE 1976 L\*u\*v\* coordinates to a color.
+fn from_luv(s: &str, l: f64, u: f64, v: f64) -> Result<Color> {
+    if l == 0.0 {
+        return Ok(Color::new(0, 0, 0));
+    }
+    let (un, vn) = white_uv();
+    from_uvy(
+        s,
+        u / (13.0 * l) + un,
+        v / (13.0 * l) + vn,
+        lightness_to_y(l),
+    )
+}
+
+/// Convert Tektronix HVC coordinates to a color.
+///
+/// `TekHVC` is a polar form of CIELUV, whose hue 0 is rotated to the "best
+/// red" chromaticity, and whose chroma is scaled by the value.
+fn from_tekhvc(s: &str, h: f64, v: f64, c: f64) -> Result<Color> {
+    // Constants of the Xcms implementation
+    const BEST_RED: (f64, f64) = (0.7127, 0.4931);
+    const CHROMA_SCALE_FACTOR: f64 = 7.50725;
+
+    if v == 0.0 {
+        return Ok(Color::new(0, 0, 0));
+    }
+    let (un, vn) = white_uv();
+    let hue = h.to_radians() + (BEST_RED.1 - vn).atan2(BEST_RED.0 - un);
+    let chroma = c / (v * CHROMA_SCALE_FACTOR);
+    from_uvy(
+        s,
+        un + hue.cos() * chroma,
+        vn + hue.sin() * chroma,
+        lightness_to_y(v),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `s`, which must be an X11 color specification.
+    fn parse_ok(s: &str) -> Color {
+        parse(s)
+            .unwrap_or_else(|| panic!("{s:?} is not recognized"))
+            .unwrap_or_else(|e| panic!("{s:?}: {e}"))
+            .0
+    }
+
+    #[test]
+    fn test_hex_to_u16() -> Result<(), String> {
+        let cases = [
+            ("0", 0),
+            ("f", 0xffff),
+            ("8", 0x8888),
+            ("00", 0),
+            ("ff", 0xffff),
+            ("80", 0x8080),
+            ("000", 0),
+            ("fff", 0xffff),
+            ("800", 0x8007),
+            ("123", 0x1231),
+            ("0000", 0),
+            ("ffff", 0xffff),
+            ("8000", 0x8000),
+            ("7fff", 0x7fff),
+            ("0080", 0x0080),
+            ("abcd", 0xabcd),
+        ];
+        for (hex, expected) in cases {
+            assert_eq!(hex_to_u16(hex)?, expected, "{hex}");
+        }
+
+        assert!(hex_to_u16("00000").is_err());
+        assert!(hex_to_u16("xyz").is_err());
+        assert!(hex_to_u16("+f").is_err());
+        assert!(hex_to_u16("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rgb() {
+        let cases = [
+            ("rgb:f/0/8", Color::new(0xffff, 0, 0x8888)),
+            ("rgb:ff/00/80", Color::new(0xffff, 0, 0x8080)),
+            ("rgb:fff/000/800", Color::new(0xffff, 0, 0x8007)),
+            ("rgb:ffff/0000/8000", Color::new(0xffff, 0, 0x8000)),
+            ("rgb:f/00/fff", Color::new(0xffff, 0, 0xffff)),
+            ("RGB:FF/00/80", Color::new(0xffff, 0, 0x8080)),
+            ("rgba:ffff/0000/8000/ffff", Color::new(0xffff, 0, 0x8000)),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_ok(s), expected, "{s}");
+        }
+    }
+
+    #[test]
+    fn test_parse_alpha() {
+        let cases = [
+            ("rgb:ff/ff/ff", 1.0),
+            ("rgba:ffff/ffff/ffff/ffff", 1.0),
+            ("rgba:ffff/ffff/ffff/0000", 0.0),
+            ("rgba:ff/ff/ff/80", f64::from(0x8080) / 65535.0),
+            ("rgb:f/f/f/8", f64::from(0x8888) / 65535.0),
+            ("CIELab:50/0/0", 1.0),
+        ];
+        for (s, expected) in cases {
+            let (_, alpha) = parse(s).unwrap().unwrap();
+            assert!((alpha - expected).abs() < 1e-9, "{s}: {alpha}");
+        }
+        assert!(parse("rgba:ff/ff/ff/gg").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_device_independent() {
+        // Compared as 8-bit colors, to allow for rounding errors
+        let cases = [
+            ("rgbi:1/0/0.5", (255, 0, 128)),
+            ("rgbi:0.0/1.0/.2", (0, 255, 51)),
+            ("RGBi:1/1/1", (255, 255, 255)),
+            ("CIEXYZ:0/0/0", (0, 0, 0)),
+            ("CIEXYZ:0.95047/1.0/1.08883", (255, 255, 255)),
+            ("CIEXYZ:0.4124564/0.2126729/0.0193339", (255, 0, 0)),
+            ("ciexyz:0.1805/0.0722/0.9505", (0, 0, 255)),
+            ("CIEuvY:0.1978/0.4683/1", (255, 255, 255)),
+            ("CIEuvY:0.4507/0.5229/0.2126729", (255, 0, 0)),
+            ("CIEuvY:0.5/0/0", (0, 0, 0)),
+            ("CIExyY:0.3127/0.3290/1", (255, 255, 255)),
+            ("CIExyY:0.3127/0.3290/0.2158605", (128, 128, 128)),
+            ("CIExyY:0.64/0.33/0.2126729", (255, 0, 0)),
+            ("CIExyY:0.3/0.6/0.7151522", (0, 255, 0)),
+            ("CIELab:0/0/0", (0, 0, 0)),
+            ("CIELab:100/0/0", (255, 255, 255)),
+            ("CIELab:53.585/0/0", (128, 128, 128)),
+            ("CIELab:53.2408/80.0925/67.2032", (255, 0, 0)),
+            ("cielab:32.3026/79.1967/-107.8637", (0, 0, 255)),
+            ("CIELuv:0/50/50", (0, 0, 0)),
+            ("CIELuv:100/0/0", (255, 255, 255)),
+            ("CIELuv:53.2408/175.0151/37.7564", (255, 0, 0)),
+            ("CIELuv:87.7347/-83.0776/107.3985", (0, 255, 0)),
+            ("TekHVC:0/0/0", (0, 0, 0)),
+            ("TekHVC:0/100/0", (255, 255, 255)),
+            ("TekHVC:180/53.585/0", (128, 128, 128)),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_ok(s).to_rgb8(), expected, "{s}");
+        }
+    }
+
+    #[test]
+    fn test_tekhvc_is_polar_cieluv() {
+        // Hue 0 points to the "best red" chromaticity, and the chroma scale
+        // factor relates TekHVC chroma to CIELUV chroma
+        let (un, vn) = white_uv();
+        let offset = (0.4931 - vn).atan2(0.7127 - un);
+        for (h, v, c) in [(0.0, 50.0, 20.0), (90.0, 70.0, 30.0), (240.0, 30.0, 10.0)] {
+            let hue = f64::to_radians(h) + offset;
+            let chroma = 13.0 * c / 7.50725;
+            assert_eq!(
+                parse_ok(&format!("TekHVC:{h}/{v}/{c}")),
+                parse_ok(&format!(
+                    "CIELuv:{v}/{}/{}",
+                    chroma * hue.cos(),
+                    chroma * hue.sin()
+                )),
+                "{h}/{v}/{c}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            "rgb:",
+            "rgb:ff/ff",
+            "rgb:ff/ff/ff/ff/ff",
+            "rgb:gggg/gggg/gggg",
+            "rgb:00000/00000/00000",
+            "rgb:ff//ff",
+            "rgbi:1/1",
+            "rgbi:1.5/0/0",
+            "rgbi:-0.1/0/0",
+            "rgbi:a/b/c",
+            "rgbi:nan/0/0",
+            "CIEXYZ:0/2/0",
+            "CIEXYZ:-1/0.5/0",
+            "CIEuvY:0.2/0/0.5",
+            "CIExyY:0.3/0/0.5",
+            "CIELab:101/0/0",
+            "CIELuv:-1/0/0",
+            "TekHVC:361/50/50",
+            "TekHVC:0/50/-1",
+        ];
+        for s in cases {
+            assert!(parse(s).is_some_and(|result| result.is_err()), "{s}");
+        }
+
+        assert!(parse("#ff0000").is_none());
+        assert!(parse("rgb(0, 0, 0)").is_none());
+        assert!(parse("hsl:0/0/0").is_none());
+    }
+}